@@ -6,6 +6,7 @@ export interface CompileRequest {
   mode: Mode;
   opt: OptLevel;
   reuse_across_call: boolean;
+  debug: boolean;
 }
 
 export const CompileResponseSchema = z.object({
@@ -13,25 +14,63 @@ export const CompileResponseSchema = z.object({
   output: z.string().optional(),
   wasm: z.string().optional(),
   error: z.string().optional(),
+  warnings: z.array(z.string()).optional(),
 });
 
 export type CompileResponse = z.infer<typeof CompileResponseSchema>;
 
+/**
+ * Run mode asks for the raw module; the server falls back to JSON for
+ * failures, so callers must check which one arrived.
+ */
+export type CompileResult =
+  | { kind: "json"; data: CompileResponse }
+  | { kind: "wasm"; response: Response; warnings: string[] };
+
 export async function compileCode(
   req: CompileRequest,
-): Promise<CompileResponse> {
+): Promise<CompileResult> {
   const resp = await fetch("/api/compile", {
     method: "POST",
-    headers: { "Content-Type": "application/json" },
+    headers: {
+      "Content-Type": "application/json",
+      Accept:
+        req.mode === "run"
+          ? "application/wasm, application/json"
+          : "application/json",
+    },
     body: JSON.stringify(req),
   });
 
-  if (!resp.ok) {
+  // Refused requests (rate limits, a full build queue, oversized packages)
+  // answer with the usual JSON failure body.
+  if (
+    !resp.ok &&
+    !resp.headers.get("Content-Type")?.startsWith("application/json")
+  ) {
     throw new Error(`server error ${resp.status}: ${resp.statusText}`);
   }
 
+  if (resp.headers.get("Content-Type")?.startsWith("application/wasm")) {
+    return {
+      kind: "wasm",
+      response: resp,
+      warnings: parseWarningsHeader(resp.headers.get("X-Compile-Warnings")),
+    };
+  }
+
   const data: unknown = await resp.json();
-  return CompileResponseSchema.parse(data);
+  return { kind: "json", data: CompileResponseSchema.parse(data) };
+}
+
+/** Raw wasm responses carry module warnings as a JSON array header. */
+function parseWarningsHeader(header: string | null): string[] {
+  if (!header) return [];
+  try {
+    return z.array(z.string()).parse(JSON.parse(header));
+  } catch {
+    return [];
+  }
 }
 
 export function b64ToBytes(b64: string): Uint8Array {
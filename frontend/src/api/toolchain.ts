@@ -0,0 +1,26 @@
+import { z } from "zod";
+
+export const TextModeSchema = z.object({
+  name: z.string(),
+  extension: z.string(),
+  label: z.string(),
+});
+
+export type TextMode = z.infer<typeof TextModeSchema>;
+
+export const ToolchainSchema = z.object({
+  modes: z.array(z.string()),
+  text_modes: z.array(TextModeSchema),
+  opt_levels: z.array(z.string()),
+});
+
+export type Toolchain = z.infer<typeof ToolchainSchema>;
+
+export async function fetchToolchain(): Promise<Toolchain> {
+  const resp = await fetch("/api/toolchain");
+  if (!resp.ok) {
+    throw new Error(`server error ${resp.status}: ${resp.statusText}`);
+  }
+  const data: unknown = await resp.json();
+  return ToolchainSchema.parse(data);
+}
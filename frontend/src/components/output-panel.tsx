@@ -10,9 +10,16 @@ import {
 } from "@/lang/output-languages";
 import { reussirDarkTheme, reussirLightTheme } from "@/lang/reussir-theme";
 import { cn } from "@/lib/utils";
-import { outputAtom, outputLabelAtom, outputModeAtom, themeAtom } from "@/store/atoms";
+import {
+  outputAtom,
+  outputLabelAtom,
+  outputModeAtom,
+  textModesAtom,
+  themeAtom,
+} from "@/store/atoms";
 
 const MODE_LANGUAGE: Record<string, string> = {
+  wat: "wat",
   "llvm-ir": "llvm-ir",
   asm: "wat",
   mlir: "mlir",
@@ -20,6 +27,7 @@ const MODE_LANGUAGE: Record<string, string> = {
 
 const MODE_EXT: Record<string, string> = {
   run: "txt",
+  wat: "wat",
   "llvm-ir": "ll",
   asm: "wat",
   mlir: "mlir",
@@ -49,13 +57,17 @@ export function OutputPanel() {
   const label = useAtomValue(outputLabelAtom);
   const mode = useAtomValue(outputModeAtom);
   const theme = useAtomValue(themeAtom);
+  const textModes = useAtomValue(textModesAtom);
+  const ext =
+    MODE_EXT[mode] ??
+    textModes.find((text) => text.name === mode)?.extension ??
+    "txt";
 
   const handleBeforeMount = useCallback((monaco: Monaco) => {
     registerOutputLanguages(monaco);
   }, []);
 
   const handleDownload = useCallback(() => {
-    const ext = MODE_EXT[mode] ?? "txt";
     const blob = new Blob([output.text], { type: "text/plain" });
     const url = URL.createObjectURL(blob);
     const a = document.createElement("a");
@@ -63,7 +75,7 @@ export function OutputPanel() {
     a.download = `output.${ext}`;
     a.click();
     URL.revokeObjectURL(url);
-  }, [mode, output.text]);
+  }, [ext, output.text]);
 
   const language = MODE_LANGUAGE[mode];
   const useEditor =
@@ -78,7 +90,7 @@ export function OutputPanel() {
             type="button"
             onClick={handleDownload}
             className="toolbar-icon ml-auto h-6 w-6 inline-flex items-center justify-center rounded-md transition-all"
-            title={`Download as .${MODE_EXT[mode] ?? "txt"}`}
+            title={`Download as .${ext}`}
           >
             <Download size={12} />
           </button>
@@ -8,11 +8,13 @@ import { useCompile } from "@/hooks/use-compile";
 import { useKeyboardShortcut } from "@/hooks/use-keyboard-shortcut";
 import { useIsMobile } from "@/hooks/use-mobile";
 import { useSharedState } from "@/hooks/use-shared-state";
+import { useToolchain } from "@/hooks/use-toolchain";
 
 export function Playground() {
   const compile = useCompile();
   useKeyboardShortcut("Enter", "ctrlOrMeta", compile);
   useSharedState();
+  useToolchain();
   const isMobile = useIsMobile();
 
   return (
@@ -6,22 +6,38 @@ import { toast } from "sonner";
 import { examples } from "@/data/examples";
 import { useCompile } from "@/hooks/use-compile";
 import { useTheme } from "@/hooks/use-theme";
-import { buildShareUrl } from "@/lib/share";
+import { createShareUrl } from "@/lib/share";
 import {
+  debugAtom,
   isCompilingAtom,
   modeAtom,
+  modesAtom,
   optLevelAtom,
+  optLevelsAtom,
   outputAtom,
   reuseAcrossCallAtom,
   runButtonTextAtom,
   selectedExampleIndexAtom,
   sourceCodeAtom,
+  textModesAtom,
   type Mode,
   type OptLevel,
 } from "@/store/atoms";
 
-const MODE_OPTIONS: { value: Mode; label: string; description: string }[] = [
+type ModeOption = { value: Mode; label: string; description: string };
+
+// Built by Rene rather than rrc, so every toolchain offers them.
+const RUN_MODE_OPTIONS: ModeOption[] = [
   { value: "run", label: "Run", description: "Execute the compiled program" },
+  {
+    value: "wat",
+    label: "Final Module",
+    description: "Show the linked, stripped module as WebAssembly text",
+  },
+];
+
+// The server's built-in text modes, offered until /api/toolchain answers.
+const TEXT_MODE_OPTIONS: ModeOption[] = [
   {
     value: "llvm-ir",
     label: "LLVM IR",
@@ -79,6 +95,7 @@ export function Toolbar() {
   const [mode, setMode] = useAtom(modeAtom);
   const [optLevel, setOptLevel] = useAtom(optLevelAtom);
   const [reuseAcrossCall, setReuseAcrossCall] = useAtom(reuseAcrossCallAtom);
+  const [debug, setDebug] = useAtom(debugAtom);
   const isCompiling = useAtomValue(isCompilingAtom);
   const buttonText = useAtomValue(runButtonTextAtom);
   const setOutput = useSetAtom(outputAtom);
@@ -86,13 +103,35 @@ export function Toolbar() {
   const { theme, toggleTheme } = useTheme();
 
   const sourceCode = useAtomValue(sourceCodeAtom);
+  const modes = useAtomValue(modesAtom);
+  const textModes = useAtomValue(textModesAtom);
+  const optLevels = useAtomValue(optLevelsAtom);
+  const modeOptions: ModeOption[] =
+    modes === null
+      ? [...RUN_MODE_OPTIONS, ...TEXT_MODE_OPTIONS]
+      : [
+          ...RUN_MODE_OPTIONS.filter((opt) => modes.includes(opt.value)),
+          ...textModes.map(
+            (text) =>
+              TEXT_MODE_OPTIONS.find((opt) => opt.value === text.name) ?? {
+                value: text.name,
+                label: text.label,
+                description: `Show rrc ${text.label} output`,
+              },
+          ),
+        ];
+  const optOptions =
+    optLevels === null
+      ? OPT_OPTIONS
+      : OPT_OPTIONS.filter((opt) => optLevels.includes(opt.value));
 
   const handleShare = async () => {
-    const url = buildShareUrl({
+    const url = await createShareUrl({
       source: sourceCode,
       mode,
       opt: optLevel,
       reuseAcrossCall: reuseAcrossCall || undefined,
+      debug: debug || undefined,
     });
     window.history.replaceState(null, "", url);
     await navigator.clipboard.writeText(url);
@@ -153,7 +192,7 @@ export function Toolbar() {
               sideOffset={4}
               align="start"
             >
-              {MODE_OPTIONS.map((opt) => (
+              {modeOptions.map((opt) => (
                 <DropdownMenu.Item
                   key={opt.value}
                   onSelect={() => handleModeSelect(opt.value)}
@@ -240,7 +279,7 @@ export function Toolbar() {
               sideOffset={4}
               align="start"
             >
-              {OPT_OPTIONS.map((opt) => (
+              {optOptions.map((opt) => (
                 <DropdownMenu.Item
                   key={opt.value}
                   onSelect={() => setOptLevel(opt.value)}
@@ -296,6 +335,25 @@ export function Toolbar() {
                   regional allocations
                 </span>
               </DropdownMenu.CheckboxItem>
+              <DropdownMenu.CheckboxItem
+                checked={debug}
+                onCheckedChange={(v) => setDebug(!!v)}
+                onSelect={(e) => e.preventDefault()}
+                className={MENU_ITEM_CLASS}
+              >
+                <span className="font-medium text-text-primary flex items-center gap-2">
+                  <span
+                    className={`inline-flex items-center justify-center h-4 w-4 rounded border text-[10px] ${debug ? "bg-accent border-accent text-accent-contrast" : "border-border-input bg-bg-input"}`}
+                  >
+                    {debug && "✓"}
+                  </span>
+                  Debug info
+                </span>
+                <span className="text-[11px] text-text-secondary pl-6">
+                  Keep function names in the module so runtime traps show
+                  Reussir functions
+                </span>
+              </DropdownMenu.CheckboxItem>
             </DropdownMenu.Content>
           </DropdownMenu.Portal>
         </DropdownMenu.Root>
@@ -5,6 +5,7 @@ import {
   modeAtom,
   optLevelAtom,
   reuseAcrossCallAtom,
+  debugAtom,
   isCompilingAtom,
   outputAtom,
   outputModeAtom,
@@ -17,10 +18,40 @@ export function useCompile() {
   const mode = useAtomValue(modeAtom);
   const opt = useAtomValue(optLevelAtom);
   const reuseAcrossCall = useAtomValue(reuseAcrossCallAtom);
+  const debug = useAtomValue(debugAtom);
   const setIsCompiling = useSetAtom(isCompilingAtom);
   const setOutput = useSetAtom(outputAtom);
   const setOutputMode = useSetAtom(outputModeAtom);
 
+  const runInBrowser = useCallback(
+    async (wasm: Uint8Array | Response, warnings: string[] = []) => {
+      setOutput({ kind: "loading", text: "Running in browser\u2026" });
+      const prefix = warnings.map((w) => `warning: module ${w}\n`).join("");
+      try {
+        const result = await runWasm(wasm);
+        let text = prefix + result.stdout;
+        if (result.stderr) {
+          if (text) text += "\n";
+          text += `--- stderr ---\n${result.stderr}`;
+        }
+        if (result.exitCode !== 0) {
+          if (text) text += "\n";
+          text += `\nProcess exited with code ${result.exitCode}.`;
+        }
+        setOutput({
+          kind: result.exitCode !== 0 ? "error" : "success",
+          text: text || "(no output)",
+        });
+      } catch (e) {
+        setOutput({
+          kind: "error",
+          text: `${prefix}WASI error: ${(e as Error).message}`,
+        });
+      }
+    },
+    [setOutput],
+  );
+
   return useCallback(async () => {
     setOutputMode(mode);
     setOutput({
@@ -30,8 +61,20 @@ export function useCompile() {
     setIsCompiling(true);
 
     try {
-      const data = await compileCode({ source, mode, opt, reuse_across_call: reuseAcrossCall });
+      const result = await compileCode({
+        source,
+        mode,
+        opt,
+        reuse_across_call: reuseAcrossCall,
+        debug,
+      });
 
+      if (result.kind === "wasm") {
+        await runInBrowser(result.response, result.warnings);
+        return;
+      }
+
+      const data = result.data;
       if (!data.success) {
         setOutput({
           kind: "error",
@@ -49,29 +92,7 @@ export function useCompile() {
       }
 
       if (data.wasm !== undefined) {
-        setOutput({ kind: "loading", text: "Running in browser\u2026" });
-        const wasmBytes = b64ToBytes(data.wasm);
-        try {
-          const result = await runWasm(wasmBytes);
-          let text = result.stdout;
-          if (result.stderr) {
-            if (text) text += "\n";
-            text += `--- stderr ---\n${result.stderr}`;
-          }
-          if (result.exitCode !== 0) {
-            if (text) text += "\n";
-            text += `\nProcess exited with code ${result.exitCode}.`;
-          }
-          setOutput({
-            kind: result.exitCode !== 0 ? "error" : "success",
-            text: text || "(no output)",
-          });
-        } catch (e) {
-          setOutput({
-            kind: "error",
-            text: `WASI error: ${(e as Error).message}`,
-          });
-        }
+        await runInBrowser(b64ToBytes(data.wasm), data.warnings);
         return;
       }
 
@@ -87,5 +108,15 @@ export function useCompile() {
     } finally {
       setIsCompiling(false);
     }
-  }, [source, mode, opt, reuseAcrossCall, setIsCompiling, setOutput, setOutputMode]);
+  }, [
+    source,
+    mode,
+    opt,
+    reuseAcrossCall,
+    debug,
+    setIsCompiling,
+    setOutput,
+    setOutputMode,
+    runInBrowser,
+  ]);
 }
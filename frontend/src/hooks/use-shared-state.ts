@@ -1,8 +1,9 @@
 import { useSetAtom } from "jotai";
 import { useEffect } from "react";
 
-import { decodeState } from "@/lib/share";
+import { loadSharedState } from "@/lib/share";
 import {
+  debugAtom,
   modeAtom,
   optLevelAtom,
   reuseAcrossCallAtom,
@@ -15,15 +16,22 @@ export function useSharedState() {
   const setMode = useSetAtom(modeAtom);
   const setOptLevel = useSetAtom(optLevelAtom);
   const setReuseAcrossCall = useSetAtom(reuseAcrossCallAtom);
+  const setDebug = useSetAtom(debugAtom);
 
   useEffect(() => {
     const hash = window.location.hash.slice(1);
     if (!hash) return;
-    const state = decodeState(hash);
-    if (!state) return;
-    setSourceCode(state.source);
-    setMode(state.mode);
-    setOptLevel(state.opt);
-    if (state.reuseAcrossCall) setReuseAcrossCall(true);
-  }, [setSourceCode, setMode, setOptLevel, setReuseAcrossCall]);
+    let cancelled = false;
+    void loadSharedState(hash).then((state) => {
+      if (!state || cancelled) return;
+      setSourceCode(state.source);
+      setMode(state.mode);
+      setOptLevel(state.opt);
+      if (state.reuseAcrossCall) setReuseAcrossCall(true);
+      if (state.debug) setDebug(true);
+    });
+    return () => {
+      cancelled = true;
+    };
+  }, [setSourceCode, setMode, setOptLevel, setReuseAcrossCall, setDebug]);
 }
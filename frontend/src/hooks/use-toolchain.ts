@@ -0,0 +1,45 @@
+import { useSetAtom } from "jotai";
+import { useEffect } from "react";
+
+import { fetchToolchain } from "@/api/toolchain";
+import {
+  modeAtom,
+  modesAtom,
+  optLevelAtom,
+  optLevelsAtom,
+  textModesAtom,
+  type OptLevel,
+} from "@/store/atoms";
+
+/** On mount, load the modes and opt levels the server's toolchain supports. */
+export function useToolchain() {
+  const setModes = useSetAtom(modesAtom);
+  const setTextModes = useSetAtom(textModesAtom);
+  const setOptLevels = useSetAtom(optLevelsAtom);
+  const setMode = useSetAtom(modeAtom);
+  const setOptLevel = useSetAtom(optLevelAtom);
+
+  useEffect(() => {
+    let cancelled = false;
+    fetchToolchain()
+      .then((toolchain) => {
+        if (cancelled) return;
+        const optLevels = toolchain.opt_levels as OptLevel[];
+        setModes(toolchain.modes);
+        setTextModes(toolchain.text_modes);
+        setOptLevels(optLevels);
+        // Fall back when a shared link or the default names something the
+        // server does not offer.
+        setMode((mode) => (toolchain.modes.includes(mode) ? mode : "run"));
+        setOptLevel((opt) =>
+          optLevels.includes(opt) ? opt : (optLevels[0] ?? opt),
+        );
+      })
+      .catch(() => {
+        // Keep the built-in modes when the server cannot describe itself.
+      });
+    return () => {
+      cancelled = true;
+    };
+  }, [setModes, setTextModes, setOptLevels, setMode, setOptLevel]);
+}
@@ -5,6 +5,7 @@ export interface SharedState {
   mode: Mode;
   opt: OptLevel;
   reuseAcrossCall?: boolean;
+  debug?: boolean;
 }
 
 /** Encode playground state into a URL-safe hash string. */
@@ -46,3 +47,72 @@ export function buildShareUrl(state: SharedState): string {
   url.hash = encodeState(state);
   return url.toString();
 }
+
+/** Hash prefix marking a server-side snippet ID rather than inline state. */
+const SNIPPET_PREFIX = "s=";
+
+interface StoredSnippet {
+  source: string;
+  mode: string;
+  opt: string;
+  reuse_across_call?: boolean;
+  debug?: boolean;
+}
+
+/**
+ * Store the state on the server and build a short URL pointing at it.
+ * Falls back to the inline hash encoding when the server refuses it.
+ */
+export async function createShareUrl(state: SharedState): Promise<string> {
+  const snippet: StoredSnippet = {
+    source: state.source,
+    mode: state.mode,
+    opt: state.opt,
+    reuse_across_call: state.reuseAcrossCall ?? false,
+    debug: state.debug || undefined,
+  };
+  try {
+    const resp = await fetch("/api/share", {
+      method: "POST",
+      headers: { "Content-Type": "application/json" },
+      body: JSON.stringify(snippet),
+    });
+    if (!resp.ok) throw new Error(`server error ${resp.status}`);
+    const { id } = (await resp.json()) as { id: string };
+    const url = new URL(window.location.href);
+    url.hash = SNIPPET_PREFIX + id;
+    return url.toString();
+  } catch {
+    return buildShareUrl(state);
+  }
+}
+
+/**
+ * Resolve a URL hash into playground state, fetching server-side snippets.
+ * Returns null for missing, expired, or malformed shares.
+ */
+export async function loadSharedState(hash: string): Promise<SharedState | null> {
+  if (!hash.startsWith(SNIPPET_PREFIX)) return decodeState(hash);
+  try {
+    const id = encodeURIComponent(hash.slice(SNIPPET_PREFIX.length));
+    const resp = await fetch(`/api/share/${id}`);
+    if (!resp.ok) return null;
+    const data = (await resp.json()) as StoredSnippet;
+    if (
+      typeof data.source !== "string" ||
+      typeof data.mode !== "string" ||
+      typeof data.opt !== "string"
+    ) {
+      return null;
+    }
+    return {
+      source: data.source,
+      mode: data.mode as SharedState["mode"],
+      opt: data.opt as SharedState["opt"],
+      reuseAcrossCall: data.reuse_across_call,
+      debug: data.debug,
+    };
+  } catch {
+    return null;
+  }
+}
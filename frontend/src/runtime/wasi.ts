@@ -43,6 +43,8 @@ export class WasiCapture {
     this._memory = memory;
   }
 
+  // The server warns about imports outside `runtime.supported_imports`;
+  // keep its default list in sync with the functions implemented here.
   buildImports(): WebAssembly.Imports {
     const self = this;
     return {
@@ -202,6 +204,22 @@ export class WasiCapture {
   }
 }
 
+async function compileModule(
+  wasm: ArrayBuffer | Uint8Array | Response,
+): Promise<WebAssembly.Module> {
+  if (wasm instanceof Response) {
+    return WebAssembly.compileStreaming(wasm);
+  }
+  const buffer: ArrayBuffer =
+    wasm instanceof ArrayBuffer
+      ? wasm
+      : (wasm.buffer.slice(
+          wasm.byteOffset,
+          wasm.byteOffset + wasm.byteLength,
+        ) as ArrayBuffer);
+  return WebAssembly.compile(buffer);
+}
+
 export interface WasmResult {
   stdout: string;
   stderr: string;
@@ -210,20 +228,15 @@ export interface WasmResult {
 
 /**
  * Compile and run a wasm32-wasip1 binary, returning its captured output.
+ *
+ * A `Response` carrying `application/wasm` is compiled while it streams in.
  */
 export async function runWasm(
-  wasmBytes: ArrayBuffer | Uint8Array,
+  wasm: ArrayBuffer | Uint8Array | Response,
   timeoutMs = 10_000,
 ): Promise<WasmResult> {
   const wasi = new WasiCapture();
-  const buffer: ArrayBuffer =
-    wasmBytes instanceof ArrayBuffer
-      ? wasmBytes
-      : (wasmBytes.buffer.slice(
-          wasmBytes.byteOffset,
-          wasmBytes.byteOffset + wasmBytes.byteLength,
-        ) as ArrayBuffer);
-  const module = await WebAssembly.compile(buffer);
+  const module = await compileModule(wasm);
   const instance = await WebAssembly.instantiate(module, wasi.buildImports());
 
   const memory = instance.exports.memory;
@@ -1,5 +1,6 @@
 import { atom } from "jotai";
 import { atomWithStorage } from "jotai/utils";
+import type { TextMode } from "@/api/toolchain";
 import { examples } from "@/data/examples";
 
 // --- Theme ---
@@ -12,14 +13,23 @@ export const sourceCodeAtom = atom(examples[0]!.source);
 // --- Toolbar selections ---
 export const selectedExampleIndexAtom = atom(0);
 
-export type Mode = "run" | "llvm-ir" | "asm" | "mlir";
+// Built-in modes, plus any text modes the server's config declares.
+export type Mode = "run" | "wat" | "llvm-ir" | "asm" | "mlir" | (string & {});
 export const modeAtom = atom<Mode>("run");
 
+// Modes, text modes, and opt levels reported by /api/toolchain; null until
+// the server has described itself, when the toolbar offers the built-in ones.
+export const modesAtom = atom<Mode[] | null>(null);
+export const textModesAtom = atom<TextMode[]>([]);
+
 export type OptLevel = "none" | "default" | "size" | "aggressive";
 export const optLevelAtom = atom<OptLevel>("none");
+export const optLevelsAtom = atom<OptLevel[] | null>(null);
 
 export const reuseAcrossCallAtom = atom(false);
 
+export const debugAtom = atom(false);
+
 // --- Compile state ---
 export const isCompilingAtom = atom(false);
 
@@ -41,14 +51,20 @@ export const outputModeAtom = atom<Mode>("run");
 // --- Derived: output panel label ---
 const MODE_LABELS: Record<string, string> = {
   run: "Output",
+  wat: "Final Module",
   "llvm-ir": "LLVM IR",
   asm: "WebAssembly",
   mlir: "MLIR",
 };
 
-export const outputLabelAtom = atom(
-  (get) => MODE_LABELS[get(outputModeAtom)] ?? "Output",
-);
+export const outputLabelAtom = atom((get) => {
+  const mode = get(outputModeAtom);
+  return (
+    MODE_LABELS[mode] ??
+    get(textModesAtom).find((text) => text.name === mode)?.label ??
+    "Output"
+  );
+});
 
 // --- Derived: button text ---
 export const runButtonTextAtom = atom((get) =>
@@ -0,0 +1,127 @@
+//! Optional bearer-token authentication for the API routes.
+//!
+//! With `auth.keys` configured, every `/api` request must carry
+//! `Authorization: Bearer <key>`; the static frontend stays reachable.
+//! Accepted requests run inside a span naming the key's label, and handlers
+//! receive the key as an [`Extension`] to apply its rate limit and timeout
+//! overrides. Keys are looked up by SHA-256 digest, so comparing a guess
+//! against the configured keys takes the same time whatever it matches.
+
+use crate::config::{ApiKeyConfig, AuthConfig, Config};
+use crate::state::AppState;
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::Instrument;
+
+/// The key a request authenticated with, when authentication is on.
+pub type ApiKey = Option<Extension<Arc<ApiKeyConfig>>>;
+
+pub struct KeyStore {
+    keys: HashMap<[u8; 32], Arc<ApiKeyConfig>>,
+}
+
+impl KeyStore {
+    pub fn new(cfg: &AuthConfig) -> Self {
+        Self {
+            keys: cfg
+                .keys
+                .iter()
+                .map(|key| (digest(&key.key), Arc::new(key.clone())))
+                .collect(),
+        }
+    }
+
+    fn lookup(&self, headers: &HeaderMap) -> Option<Arc<ApiKeyConfig>> {
+        let token = headers
+            .get(header::AUTHORIZATION)?
+            .to_str()
+            .ok()?
+            .strip_prefix("Bearer ")?
+            .trim();
+        self.keys.get(&digest(token)).cloned()
+    }
+}
+
+fn digest(key: &str) -> [u8; 32] {
+    Sha256::digest(key.as_bytes()).into()
+}
+
+#[derive(Serialize)]
+struct Unauthorized {
+    success: bool,
+    error: &'static str,
+    reason: &'static str,
+}
+
+/// Route layer rejecting API requests without a valid key.
+pub async fn require_key(State(state): State<AppState>, mut req: Request, next: Next) -> Response {
+    if state.keys.keys.is_empty() {
+        return next.run(req).await;
+    }
+    let Some(key) = state.keys.lookup(req.headers()) else {
+        let mut response = (
+            StatusCode::UNAUTHORIZED,
+            Json(Unauthorized {
+                success: false,
+                error: "a valid API key is required",
+                reason: "unauthorized",
+            }),
+        )
+            .into_response();
+        response
+            .headers_mut()
+            .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+        return response;
+    };
+    let span = tracing::info_span!("api_key", label = %key.label);
+    req.extensions_mut().insert(key);
+    next.run(req).instrument(span).await
+}
+
+/// Deadline for a compile request, after the key's override.
+pub fn request_timeout(cfg: &Config, key: &ApiKey) -> Duration {
+    let secs = key
+        .as_ref()
+        .and_then(|Extension(key)| key.request_timeout_secs)
+        .unwrap_or(cfg.compiler.request_timeout_secs);
+    Duration::from_secs(secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_are_matched_by_bearer_token() {
+        let store = KeyStore::new(&AuthConfig {
+            keys: vec![ApiKeyConfig {
+                key: "0123456789abcdef".to_owned(),
+                label: "ci".to_owned(),
+                rate_limit: Default::default(),
+                request_timeout_secs: None,
+                admin: false,
+            }],
+            keys_file: None,
+        });
+        let mut headers = HeaderMap::new();
+        assert!(store.lookup(&headers).is_none());
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Bearer 0123456789abcdef"),
+        );
+        assert_eq!(store.lookup(&headers).unwrap().label, "ci");
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Basic 0123456789abcdef"),
+        );
+        assert!(store.lookup(&headers).is_none());
+    }
+}
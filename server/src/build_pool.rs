@@ -0,0 +1,411 @@
+//! Pools of Rene build directories.
+//!
+//! Rene keeps an exclusive redb database in its build directory, so a
+//! directory serves one build at a time. Each toolchain gets
+//! `compiler.build_slots` directories, each with its own baked runtime, and a
+//! build takes whichever is free. Slot 0 is the toolchain's `build_dir`
+//! itself, so a single-slot setup keeps using the existing runtime; further
+//! slots live under `<build_dir>/slots/<n>`.
+//!
+//! At most `compiler.queue_depth` builds wait for a directory; past that,
+//! builds fail fast with [`Busy`] instead of piling up until clients give up.
+//! Waiting builds are served in arrival order and report their place in line
+//! through [`Progress`].
+//!
+//! A directory counts as baked when an executable build succeeds in it, or
+//! when the pool finds a runtime an earlier run left behind, so a restart
+//! over a persistent `build_dir` is ready at once.
+
+use crate::compile::TARGET;
+use crate::metrics::METRICS;
+use crate::progress::Progress;
+use anyhow::{Context, Result};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock};
+use std::time::Instant;
+use tokio::sync::{watch, Mutex, Notify, OwnedMutexGuard, Semaphore, SemaphorePermit};
+
+/// Suggested wait before retrying a build rejected with [`Busy`].
+pub const RETRY_AFTER_SECS: u64 = 5;
+
+/// How deep under a build directory to look for Cargo's target directory.
+const TARGET_SEARCH_DEPTH: usize = 4;
+
+/// Entries of a build directory that hold no Cargo build output; `slots` and
+/// `toolchains` belong to other build directories.
+const NOT_BUILD_OUTPUT: &[&str] = &["cache", "cargo-home", "requests", "slots", "toolchains"];
+
+/// One lock per Rene build directory, keyed by path. A pool never hands out a
+/// directory twice; the locks also keep separate pools over the same
+/// directories apart.
+static DIR_LOCKS: LazyLock<std::sync::Mutex<HashMap<PathBuf, Arc<Mutex<()>>>>> =
+    LazyLock::new(Default::default);
+
+fn dir_lock(build_dir: &Path) -> Arc<Mutex<()>> {
+    DIR_LOCKS
+        .lock()
+        .unwrap()
+        .entry(build_dir.to_owned())
+        .or_default()
+        .clone()
+}
+
+#[derive(Debug)]
+pub struct BuildPool {
+    /// Toolchain name, for metrics.
+    toolchain: String,
+    dirs: Vec<PathBuf>,
+    /// Whether each directory holds a baked `wasm32-wasip1` runtime, found on
+    /// disk at startup or left by an executable build since.
+    baked: Vec<AtomicBool>,
+    /// Indices into `dirs` not currently in use.
+    free: std::sync::Mutex<BTreeSet<usize>>,
+    available: Semaphore,
+    queue_depth: usize,
+    /// Tickets of builds waiting for a directory, oldest first.
+    waiting: std::sync::Mutex<VecDeque<u64>>,
+    next_ticket: AtomicU64,
+    /// Signalled whenever a build leaves the queue.
+    queue_changed: watch::Sender<()>,
+    /// Signalled whenever a directory returns to `free`.
+    released: Notify,
+}
+
+/// The build queue is full.
+#[derive(Debug)]
+pub struct Busy;
+
+impl std::fmt::Display for Busy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "the server is busy; try again in {RETRY_AFTER_SECS} seconds"
+        )
+    }
+}
+
+impl std::error::Error for Busy {}
+
+/// Exclusive use of one build directory; returned to the pool on drop.
+pub struct BuildSlot<'a> {
+    pool: &'a BuildPool,
+    index: usize,
+    _lock: OwnedMutexGuard<()>,
+    _permit: SemaphorePermit<'a>,
+}
+
+impl BuildPool {
+    /// Create `slots` build directories for `toolchain`, rooted at
+    /// `build_dir`, with room for `queue_depth` waiting builds.
+    pub fn new(
+        toolchain: &str,
+        build_dir: &Path,
+        slots: usize,
+        queue_depth: usize,
+    ) -> Result<Self> {
+        let dirs: Vec<PathBuf> = (0..slots)
+            .map(|slot| match slot {
+                0 => build_dir.to_owned(),
+                _ => build_dir.join("slots").join(slot.to_string()),
+            })
+            .collect();
+        for dir in &dirs {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("cannot create build slot {}", dir.display()))?;
+        }
+        Ok(Self {
+            toolchain: toolchain.to_owned(),
+            free: std::sync::Mutex::new((0..slots).collect()),
+            available: Semaphore::new(slots),
+            queue_depth,
+            waiting: Default::default(),
+            next_ticket: AtomicU64::new(0),
+            queue_changed: watch::Sender::new(()),
+            released: Notify::new(),
+            baked: dirs
+                .iter()
+                .map(|dir| AtomicBool::new(runtime_on_disk(dir, TARGET_SEARCH_DEPTH)))
+                .collect(),
+            dirs,
+        })
+    }
+
+    /// Wait for a free build directory, reporting the queue position to
+    /// `progress`. Lower slots are preferred, since they are the most likely
+    /// to have a runtime baked already.
+    pub async fn acquire(&self, progress: &Progress) -> Result<BuildSlot<'_>, Busy> {
+        let started = Instant::now();
+        let permit = match self.available.try_acquire() {
+            Ok(permit) => permit,
+            Err(_) => self.wait(progress).await?,
+        };
+        let index = self
+            .free
+            .lock()
+            .unwrap()
+            .pop_first()
+            .expect("a permit guarantees a free slot");
+        let lock = dir_lock(&self.dirs[index]).lock_owned().await;
+        METRICS
+            .queue_wait
+            .with_label_values(&[&self.toolchain])
+            .observe(started.elapsed().as_secs_f64());
+        Ok(BuildSlot {
+            pool: self,
+            index,
+            _lock: lock,
+            _permit: permit,
+        })
+    }
+
+    /// Wait for build directory `index` itself, so the warm-up can bake each
+    /// directory even while other builds run. Does not count against the
+    /// queue depth.
+    pub async fn acquire_slot(&self, index: usize) -> BuildSlot<'_> {
+        loop {
+            let released = self.released.notified();
+            tokio::pin!(released);
+            released.as_mut().enable();
+            let permit = self
+                .available
+                .acquire()
+                .await
+                .expect("build pool semaphore is never closed");
+            if self.free.lock().unwrap().remove(&index) {
+                let lock = dir_lock(&self.dirs[index]).lock_owned().await;
+                return BuildSlot {
+                    pool: self,
+                    index,
+                    _lock: lock,
+                    _permit: permit,
+                };
+            }
+            // Another directory is free; leave it to the builds waiting.
+            drop(permit);
+            released.await;
+        }
+    }
+
+    async fn wait(&self, progress: &Progress) -> Result<SemaphorePermit<'_>, Busy> {
+        let ticket = {
+            let mut waiting = self.waiting.lock().unwrap();
+            if waiting.len() >= self.queue_depth {
+                return Err(Busy);
+            }
+            let ticket = self.next_ticket.fetch_add(1, Ordering::Relaxed);
+            waiting.push_back(ticket);
+            ticket
+        };
+        let _queued = Queued { pool: self, ticket };
+        let mut changed = self.queue_changed.subscribe();
+        // The semaphore queues waiters in order, so keep one acquire future
+        // across position updates rather than re-joining the line.
+        let acquire = self.available.acquire();
+        tokio::pin!(acquire);
+        let mut reported = None;
+        loop {
+            let position = self.position(ticket);
+            if reported != Some(position) {
+                progress.queue(position);
+                reported = Some(position);
+            }
+            tokio::select! {
+                permit = &mut acquire => {
+                    return Ok(permit.expect("build pool semaphore is never closed"));
+                }
+                _ = changed.changed() => {}
+            }
+        }
+    }
+
+    pub fn slots(&self) -> usize {
+        self.dirs.len()
+    }
+
+    /// Whether build directory `index` is known to hold a baked runtime.
+    pub fn is_baked(&self, index: usize) -> bool {
+        self.baked[index].load(Ordering::Relaxed)
+    }
+
+    /// Number of build directories known to hold a baked runtime.
+    pub fn baked_slots(&self) -> usize {
+        self.baked
+            .iter()
+            .filter(|baked| baked.load(Ordering::Relaxed))
+            .count()
+    }
+
+    /// 1-based place of `ticket` in the queue.
+    fn position(&self, ticket: u64) -> usize {
+        let waiting = self.waiting.lock().unwrap();
+        waiting
+            .iter()
+            .take_while(|&&queued| queued != ticket)
+            .count()
+            + 1
+    }
+}
+
+/// Whether `dir` holds Cargo output for the Reussir runtime,
+/// `<target>/wasm32-wasip1/<profile>/[deps/]libreussir_rt*`, within `depth`
+/// levels.
+fn runtime_on_disk(dir: &Path, depth: usize) -> bool {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return false;
+    };
+    entries
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_dir()))
+        .any(|entry| {
+            let name = entry.file_name();
+            if name == TARGET {
+                has_runtime_artifact(&entry.path())
+            } else {
+                depth > 0
+                    && !NOT_BUILD_OUTPUT.iter().any(|skipped| name == *skipped)
+                    && runtime_on_disk(&entry.path(), depth - 1)
+            }
+        })
+}
+
+fn has_runtime_artifact(target_dir: &Path) -> bool {
+    let Ok(profiles) = std::fs::read_dir(target_dir) else {
+        return false;
+    };
+    profiles.filter_map(Result::ok).any(|profile| {
+        let profile = profile.path();
+        [profile.clone(), profile.join("deps")].iter().any(|dir| {
+            std::fs::read_dir(dir).is_ok_and(|files| {
+                files.filter_map(Result::ok).any(|file| {
+                    file.file_name()
+                        .to_str()
+                        .is_some_and(|name| name.starts_with("libreussir_rt"))
+                })
+            })
+        })
+    })
+}
+
+/// A build's place in the queue; leaving it, served or cancelled, moves
+/// everyone behind it up.
+struct Queued<'a> {
+    pool: &'a BuildPool,
+    ticket: u64,
+}
+
+impl Drop for Queued<'_> {
+    fn drop(&mut self) {
+        self.pool
+            .waiting
+            .lock()
+            .unwrap()
+            .retain(|&queued| queued != self.ticket);
+        self.pool.queue_changed.send_replace(());
+    }
+}
+
+impl BuildSlot<'_> {
+    pub fn dir(&self) -> &Path {
+        &self.pool.dirs[self.index]
+    }
+
+    /// Record that an executable Rene build succeeded in this directory.
+    /// Target-less builds do not bake the `wasm32-wasip1` runtime.
+    pub fn mark_baked(&self) {
+        self.pool.baked[self.index].store(true, Ordering::Relaxed);
+    }
+}
+
+impl Drop for BuildSlot<'_> {
+    fn drop(&mut self) {
+        // Runs before the permit is released, so the next waiter finds it.
+        self.pool.free.lock().unwrap().insert(self.index);
+        self.pool.released.notify_waiters();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progress::ProgressEvent;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn hands_out_each_slot_once() {
+        let root = tempfile::tempdir().unwrap();
+        let pool = BuildPool::new("test", root.path(), 2, 1).unwrap();
+        let progress = Progress::disabled();
+        let first = pool.acquire(&progress).await.unwrap();
+        let second = pool.acquire(&progress).await.unwrap();
+        assert_eq!(first.dir(), root.path());
+        assert_eq!(second.dir(), root.path().join("slots/1"));
+        assert!(second.dir().is_dir());
+        second.mark_baked();
+        assert_eq!((pool.baked_slots(), pool.slots()), (1, 2));
+
+        let waiting =
+            tokio::time::timeout(Duration::from_millis(10), pool.acquire(&progress)).await;
+        assert!(waiting.is_err());
+        drop(first);
+        assert_eq!(pool.acquire(&progress).await.unwrap().dir(), root.path());
+    }
+
+    #[tokio::test]
+    async fn rejects_builds_past_the_queue_depth() {
+        let root = tempfile::tempdir().unwrap();
+        let pool = BuildPool::new("test", root.path(), 1, 1).unwrap();
+        let (events_tx, mut events_rx) = tokio::sync::mpsc::unbounded_channel();
+        let progress = Progress::new(events_tx);
+        let held = pool.acquire(&progress).await.unwrap();
+
+        let queued = pool.acquire(&progress);
+        tokio::pin!(queued);
+        assert!(poll_briefly(queued.as_mut()).await.is_none());
+        assert!(matches!(
+            events_rx.try_recv(),
+            Ok(ProgressEvent::Queue { position: 1 })
+        ));
+        assert!(matches!(pool.acquire(&progress).await, Err(Busy)));
+
+        drop(held);
+        assert_eq!(queued.await.unwrap().dir(), root.path());
+        assert!(pool.waiting.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn warm_up_waits_for_the_slot_it_names() {
+        let root = tempfile::tempdir().unwrap();
+        let pool = BuildPool::new("test", root.path(), 2, 1).unwrap();
+        let second = pool.acquire_slot(1).await;
+        assert_eq!(second.dir(), root.path().join("slots/1"));
+
+        let held = pool.acquire(&Progress::disabled()).await.unwrap();
+        let first = pool.acquire_slot(0);
+        tokio::pin!(first);
+        assert!(poll_briefly(first.as_mut()).await.is_none());
+        drop(second);
+        assert!(poll_briefly(first.as_mut()).await.is_none());
+        drop(held);
+        assert_eq!(first.await.dir(), root.path());
+    }
+
+    #[test]
+    fn finds_runtimes_baked_by_earlier_runs() {
+        let root = tempfile::tempdir().unwrap();
+        let release = root.path().join("slots/1/target/wasm32-wasip1/release");
+        std::fs::create_dir_all(&release).unwrap();
+        std::fs::write(release.join("libreussir_rt.a"), []).unwrap();
+        let pool = BuildPool::new("test", root.path(), 2, 1).unwrap();
+        assert!(!pool.baked[0].load(Ordering::Relaxed));
+        assert!(pool.baked[1].load(Ordering::Relaxed));
+    }
+
+    /// `future`'s output if it finishes within 10ms.
+    async fn poll_briefly<F: std::future::Future + Unpin>(future: F) -> Option<F::Output> {
+        tokio::time::timeout(Duration::from_millis(10), future)
+            .await
+            .ok()
+    }
+}
@@ -0,0 +1,295 @@
+//! Content-addressed cache of compile artifacts.
+//!
+//! Entries live in `compiler.cache_dir`, one file per key, named by the
+//! hex SHA-256 of everything that can influence the output: the package
+//! sources, mode, optimization level, `reuse_across_call`, and a fingerprint
+//! of the toolchain binaries. Total size is capped; the least recently used
+//! entries are evicted first. File modification times record last use so the
+//! order survives restarts.
+//!
+//! The directory lies outside every toolchain's `build_dir`, so no sandboxed
+//! tool can rewrite artifacts served to other users. File IO runs on the
+//! blocking pool; the index lock is held only while the index changes.
+
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+use tempfile::NamedTempFile;
+
+/// Hex SHA-256 identifying one cache entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheKey(String);
+
+impl CacheKey {
+    pub fn builder() -> CacheKeyBuilder {
+        CacheKeyBuilder(Sha256::new())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Key for auxiliary data stored alongside this entry.
+    pub fn derived(&self, name: &str) -> CacheKey {
+        CacheKey::builder().field(&self.0).field(name).finish()
+    }
+}
+
+pub struct CacheKeyBuilder(Sha256);
+
+impl CacheKeyBuilder {
+    /// Append one field. Fields are length-prefixed, so `("ab", "c")` and
+    /// `("a", "bc")` hash differently.
+    pub fn field(mut self, bytes: impl AsRef<[u8]>) -> Self {
+        let bytes = bytes.as_ref();
+        self.0.update((bytes.len() as u64).to_le_bytes());
+        self.0.update(bytes);
+        self
+    }
+
+    pub fn finish(self) -> CacheKey {
+        CacheKey(format!("{:x}", self.0.finalize()))
+    }
+}
+
+pub struct CompileCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    index: Mutex<Index>,
+}
+
+#[derive(Default)]
+struct Index {
+    entries: HashMap<String, Entry>,
+    total_bytes: u64,
+}
+
+struct Entry {
+    size: u64,
+    last_used: SystemTime,
+}
+
+impl CompileCache {
+    /// Open the cache in `dir`, indexing entries left by earlier runs. A
+    /// `max_bytes` of zero disables caching.
+    pub fn open(dir: PathBuf, max_bytes: u64) -> Result<Self> {
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("cannot create cache directory: {}", dir.display()))?;
+        let mut index = Index::default();
+        for entry in std::fs::read_dir(&dir)
+            .with_context(|| format!("cannot read cache directory: {}", dir.display()))?
+        {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            let name = entry.file_name();
+            let Some(name) = name.to_str().filter(|name| is_key(name)) else {
+                continue;
+            };
+            if !metadata.is_file() {
+                continue;
+            }
+            index.total_bytes += metadata.len();
+            index.entries.insert(
+                name.to_owned(),
+                Entry {
+                    size: metadata.len(),
+                    last_used: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                },
+            );
+        }
+        let cache = Self {
+            dir,
+            max_bytes,
+            index: Mutex::new(index),
+        };
+        let evicted = cache.evict(&mut cache.index.lock().unwrap());
+        remove_files(evicted);
+        Ok(cache)
+    }
+
+    pub async fn get(&self, key: &CacheKey) -> Option<Vec<u8>> {
+        if self.max_bytes == 0
+            || !self
+                .index
+                .lock()
+                .unwrap()
+                .entries
+                .contains_key(key.as_str())
+        {
+            return None;
+        }
+        let path = self.path(key);
+        let now = SystemTime::now();
+        let read = tokio::task::spawn_blocking(move || {
+            let bytes = std::fs::read(&path)?;
+            if let Err(error) = std::fs::File::options()
+                .write(true)
+                .open(&path)
+                .and_then(|file| file.set_modified(now))
+            {
+                tracing::debug!("cannot touch cache entry {}: {error}", path.display());
+            }
+            Ok::<_, std::io::Error>(bytes)
+        })
+        .await
+        .unwrap_or_else(|error| Err(std::io::Error::other(error)));
+
+        let mut index = self.index.lock().unwrap();
+        match read {
+            Ok(bytes) => {
+                if let Some(entry) = index.entries.get_mut(key.as_str()) {
+                    entry.last_used = now;
+                }
+                Some(bytes)
+            }
+            Err(error) => {
+                // Evicted while it was being read, or unreadable.
+                if let Some(entry) = index.entries.remove(key.as_str()) {
+                    tracing::warn!("dropping unreadable cache entry {}: {error}", key.as_str());
+                    index.total_bytes -= entry.size;
+                }
+                None
+            }
+        }
+    }
+
+    pub async fn insert(&self, key: &CacheKey, bytes: Vec<u8>) {
+        let size = bytes.len() as u64;
+        if self.max_bytes == 0 || size > self.max_bytes {
+            return;
+        }
+        let dir = self.dir.clone();
+        let path = self.path(key);
+        let written = tokio::task::spawn_blocking(move || {
+            NamedTempFile::new_in(&dir)
+                .and_then(|mut file| {
+                    std::io::Write::write_all(&mut file, &bytes)?;
+                    file.persist(&path).map_err(|error| error.error)
+                })
+                .with_context(|| format!("cannot write cache entry {}", path.display()))
+        })
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|written| written);
+        if let Err(error) = written {
+            tracing::warn!("{error:#}");
+            return;
+        }
+
+        let evicted = {
+            let mut index = self.index.lock().unwrap();
+            let previous = index.entries.insert(
+                key.as_str().to_owned(),
+                Entry {
+                    size,
+                    last_used: SystemTime::now(),
+                },
+            );
+            index.total_bytes += size;
+            if let Some(previous) = previous {
+                index.total_bytes -= previous.size;
+            }
+            self.evict(&mut index)
+        };
+        if !evicted.is_empty() {
+            let _ = tokio::task::spawn_blocking(move || remove_files(evicted)).await;
+        }
+    }
+
+    /// Drop least recently used entries from `index` until it fits, returning
+    /// the files to delete once the lock is released.
+    fn evict(&self, index: &mut Index) -> Vec<PathBuf> {
+        let mut evicted = Vec::new();
+        while index.total_bytes > self.max_bytes {
+            let Some(oldest) = index
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            let entry = index.entries.remove(&oldest).expect("oldest entry exists");
+            index.total_bytes -= entry.size;
+            evicted.push(self.dir.join(&oldest));
+        }
+        evicted
+    }
+
+    fn path(&self, key: &CacheKey) -> PathBuf {
+        self.dir.join(key.as_str())
+    }
+}
+
+fn remove_files(paths: Vec<PathBuf>) {
+    for path in paths {
+        if let Err(error) = std::fs::remove_file(&path) {
+            tracing::warn!("cannot evict cache entry {}: {error}", path.display());
+        }
+    }
+}
+
+fn is_key(name: &str) -> bool {
+    name.len() == 64 && name.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+/// Fingerprint one toolchain file by path, size, and modification time.
+/// Replacing a nightly in place changes at least the modification time.
+pub fn fingerprint_file(key: CacheKeyBuilder, path: &Path) -> Result<CacheKeyBuilder> {
+    let metadata = std::fs::metadata(path)
+        .with_context(|| format!("cannot stat toolchain file: {}", path.display()))?;
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .unwrap_or_default();
+    Ok(key
+        .field(path.as_os_str().as_encoded_bytes())
+        .field(metadata.len().to_le_bytes())
+        .field(modified.as_nanos().to_le_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(name: &str) -> CacheKey {
+        CacheKey::builder().field(name).finish()
+    }
+
+    #[tokio::test]
+    async fn evicts_least_recently_used_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = CompileCache::open(dir.path().to_owned(), 8).unwrap();
+        cache.insert(&key("a"), b"1234".to_vec()).await;
+        cache.insert(&key("b"), b"5678".to_vec()).await;
+        assert!(cache.get(&key("a")).await.is_some());
+        cache.insert(&key("c"), b"9abc".to_vec()).await;
+
+        assert_eq!(cache.get(&key("a")).await.as_deref(), Some(&b"1234"[..]));
+        assert!(cache.get(&key("b")).await.is_none());
+        assert!(!dir.path().join(key("b").as_str()).exists());
+        assert_eq!(cache.get(&key("c")).await.as_deref(), Some(&b"9abc"[..]));
+    }
+
+    #[tokio::test]
+    async fn reopening_restores_the_index() {
+        let dir = tempfile::tempdir().unwrap();
+        CompileCache::open(dir.path().to_owned(), 64)
+            .unwrap()
+            .insert(&key("a"), b"payload".to_vec())
+            .await;
+        let cache = CompileCache::open(dir.path().to_owned(), 64).unwrap();
+        assert_eq!(cache.get(&key("a")).await.as_deref(), Some(&b"payload"[..]));
+    }
+
+    #[test]
+    fn key_fields_are_length_prefixed() {
+        let ab_c = CacheKey::builder().field("ab").field("c").finish();
+        let a_bc = CacheKey::builder().field("a").field("bc").finish();
+        assert_ne!(ab_c, a_bc);
+    }
+}
@@ -0,0 +1,143 @@
+//! `/api/compare` request handler.
+//!
+//! Builds one package under two option sets, for example `-O size` against
+//! `-O aggressive` or two toolchains, and returns both results with a unified
+//! diff and the size difference. Run-mode modules are diffed as WebAssembly
+//! text. The diff and sizes are present only when both builds succeed.
+
+use crate::auth::{self, ApiKey};
+use crate::compile::{self, CompileOptions, CompileRequest, CompileResponse, RequestJson};
+use crate::progress::Progress;
+use crate::rate_limit::Budget;
+use crate::state::AppState;
+use axum::extract::{ConnectInfo, State};
+use axum::http::HeaderMap;
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use serde::{Deserialize, Serialize};
+use similar::TextDiff;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+
+/// Lines of unchanged context around each diff hunk.
+const DIFF_CONTEXT: usize = 3;
+
+#[derive(Deserialize)]
+pub struct CompareRequest {
+    #[serde(default)]
+    source: String,
+    #[serde(default)]
+    files: BTreeMap<String, String>,
+    mode: String,
+    left: CompileOptions,
+    right: CompileOptions,
+}
+
+#[derive(Serialize)]
+pub struct CompareResponse {
+    left: CompileResponse,
+    right: CompileResponse,
+    /// Unified diff from the left output to the right one; empty when they
+    /// are identical.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    diff: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<SizeDelta>,
+}
+
+/// Output sizes in bytes: the module for run mode, the text otherwise.
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct SizeDelta {
+    left: usize,
+    right: usize,
+    /// `right - left`.
+    delta: i64,
+}
+
+pub async fn handle(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    key: ApiKey,
+    headers: HeaderMap,
+    RequestJson(req): RequestJson<CompareRequest>,
+) -> Response {
+    // Both builds count against the budget.
+    let budget = Budget::for_mode(&req.mode);
+    let api_key = key.as_ref().map(|Extension(key)| key.as_ref());
+    if let Err(limited) = state.limits.check(peer, &headers, api_key, budget, 2) {
+        return limited.into_response();
+    }
+    let timeout = auth::request_timeout(&state.config, &key);
+    let left = CompileRequest::new(
+        req.source.clone(),
+        req.files.clone(),
+        req.mode.clone(),
+        req.left,
+    );
+    let right = CompileRequest::new(req.source, req.files, req.mode, req.right);
+    let progress = Progress::disabled();
+    let (left, right) = tokio::join!(
+        compile::compile(&state, &left, timeout, &progress),
+        compile::compile(&state, &right, timeout, &progress),
+    );
+    let (diff, size) = match (comparable(&left).await, comparable(&right).await) {
+        (Some((left_text, left_size)), Some((right_text, right_size))) => (
+            Some(unified_diff(&left_text, &right_text)),
+            Some(SizeDelta {
+                left: left_size,
+                right: right_size,
+                delta: right_size as i64 - left_size as i64,
+            }),
+        ),
+        _ => (None, None),
+    };
+    // A refused side refuses the comparison.
+    let refusal = [&left, &right]
+        .into_iter()
+        .find_map(|response| Some((response.reason?, response.retry_after)));
+    let body = Json(CompareResponse {
+        left,
+        right,
+        diff,
+        size,
+    })
+    .into_response();
+    match refusal {
+        Some((reason, retry_after)) => compile::refused(reason, retry_after, body),
+        None => body,
+    }
+}
+
+/// Diffable text and byte size of a successful result.
+async fn comparable(response: &CompileResponse) -> Option<(String, usize)> {
+    if let Some(wasm) = &response.wasm {
+        let text = compile::disassemble(wasm.clone())
+            .await
+            .inspect_err(|error| tracing::warn!("cannot disassemble module for diff: {error:#}"))
+            .ok()?;
+        return Some((text, wasm.len()));
+    }
+    let output = response.output.as_ref()?;
+    Some((output.clone(), output.len()))
+}
+
+fn unified_diff(left: &str, right: &str) -> String {
+    TextDiff::from_lines(left, right)
+        .unified_diff()
+        .context_radius(DIFF_CONTEXT)
+        .header("left", "right")
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diffs_text_outputs() {
+        let diff = unified_diff("a\nb\nc\n", "a\nB\nc\n");
+        assert!(diff.starts_with("--- left\n+++ right\n"));
+        assert!(diff.contains("-b\n+B\n"));
+        assert_eq!(unified_diff("same\n", "same\n"), "");
+    }
+}
@@ -1,54 +1,328 @@
-//! `/api/compile` request handler.
+//! `/api/compile` and `/api/compile/stream` request handlers.
 //!
 //! Rene owns executable builds, including the embedded Reussir runtime and
 //! polymorphic FFI. Run mode asks Rene for a `wasm32-wasip1` executable,
 //! strips it with `llvm-strip`, and returns it to the browser. Text modes ask
 //! Rene to prepare the matching runtime/toolchain, then invoke the nightly
 //! `rrc` binary with Rene's reported PolyFFI library directories.
+//!
+//! The streaming route runs the same pipeline but reports phase transitions
+//! and tool output lines as server-sent events before the final response.
+//!
+//! `wat` mode runs the run-mode pipeline and disassembles the final stripped
+//! module in-process, showing everything the runtime and linker contributed.
+//!
+//! Clients that send `Accept: application/wasm` receive a successful run-mode
+//! module as the raw response body instead of base64 inside JSON, which lets
+//! browsers use `WebAssembly.instantiateStreaming`.
 
-use crate::config::Config;
+use crate::auth::{self, ApiKey};
+use crate::build_pool::{BuildSlot, Busy, RETRY_AFTER_SECS};
+use crate::cache::{self, CacheKey};
+use crate::config::{Config, EmitModeConfig, LimitsConfig, ToolchainConfig};
+use crate::diagnostics::{self, Diagnostic};
+use crate::metrics::METRICS;
+use crate::module_interface::{self, ModuleInterface};
+use crate::progress::{Phase, Progress, Stream};
+use crate::rate_limit::Budget;
+use crate::size_report::{self, SizeReport};
+use crate::state::AppState;
+use crate::toolchain::{ReneCli, Toolchain};
 use anyhow::{Context, Result};
-use axum::extract::State;
-use axum::Json;
+use axum::extract::rejection::JsonRejection;
+use axum::extract::{ConnectInfo, FromRequest, Request, State};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
 use base64::{engine::general_purpose::STANDARD as B64, Engine};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::convert::Infallible;
 use std::ffi::{OsStr, OsString};
-use std::path::{Path, PathBuf};
-use std::sync::Arc;
-use std::time::Duration;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::path::{Component, Path, PathBuf};
+use std::process::Stdio;
+use std::time::{Duration, Instant};
 use tempfile::{Builder as TempBuilder, TempDir};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
 use tokio::process::Command;
-use tokio::sync::Mutex;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use tokio_stream::StreamExt;
+use tracing::Instrument;
 
-const TARGET: &str = "wasm32-wasip1";
+pub const TARGET: &str = "wasm32-wasip1";
+/// Modes built by [`compile_run`]; available with every toolchain.
+pub const RUN_MODES: &[&str] = &["run", "wat"];
+/// Optimization levels the playground offers, narrowed at startup to those
+/// the installed rrc accepts. Unknown request values fall back to the first.
+pub const OPT_LEVELS: &[&str] = &["none", "default", "size", "aggressive"];
 const PROFILE: &str = "playground";
-static RENE_LOCK: Mutex<()> = Mutex::const_new(());
+/// Package entry module, relative to `src/`.
+const ENTRY_FILE: &str = "lib.rr";
+/// Package that warm-up builds bake the runtime with.
+const WARM_UP_SOURCE: &str = "#[main]\npub fn entry() {}\n";
+const WASM_MIME: &str = "application/wasm";
 
 #[derive(Deserialize)]
 pub struct CompileRequest {
+    /// Contents of the entry module, `src/lib.rr`. May be omitted when
+    /// `files` provides `lib.rr` itself.
+    #[serde(default)]
     source: String,
+    /// Additional package modules keyed by path relative to `src/`,
+    /// e.g. `"util/math.rr"`.
+    #[serde(default)]
+    files: BTreeMap<String, String>,
     mode: String,
+    #[serde(flatten)]
+    options: CompileOptions,
+}
+
+/// Build settings, which `/api/compare` varies between its two builds.
+#[derive(Deserialize, Clone)]
+pub struct CompileOptions {
     /// Optimization level: "none" | "default" | "size" | "aggressive".
     #[serde(default = "default_opt")]
     opt: String,
     /// Pass `--reuse-across-call` through Rene to rrc.
     #[serde(default)]
     reuse_across_call: bool,
+    /// Build run-mode modules with debug info and keep their `name` section,
+    /// so traps name Reussir functions instead of `wasm-function[N]`.
+    #[serde(default)]
+    debug: bool,
+    /// Configured toolchain to compile with; the server default when absent.
+    #[serde(default)]
+    toolchain: Option<String>,
 }
 
 fn default_opt() -> String {
     "none".to_owned()
 }
 
+impl CompileOptions {
+    /// Defaults at optimization level `opt`, as the frontend sends them.
+    pub fn new(opt: &str) -> Self {
+        Self {
+            opt: opt.to_owned(),
+            reuse_across_call: false,
+            debug: false,
+            toolchain: None,
+        }
+    }
+}
+
+impl CompileRequest {
+    pub fn new(
+        source: String,
+        files: BTreeMap<String, String>,
+        mode: String,
+        options: CompileOptions,
+    ) -> Self {
+        Self {
+            source,
+            files,
+            mode,
+            options,
+        }
+    }
+
+    /// Every package source keyed by its validated path relative to `src/`.
+    /// Checked against `limits` before anything is written to disk.
+    fn package_files(&self, limits: &LimitsConfig) -> Result<BTreeMap<PathBuf, &str>> {
+        let count = self.files.len() + usize::from(!self.source.is_empty());
+        if count > limits.max_files {
+            return Err(Rejected::new(
+                FailureReason::TooManyFiles,
+                format!(
+                    "package has {count} source files; the limit is {}",
+                    limits.max_files
+                ),
+            )
+            .into());
+        }
+        let size: usize = self.source.len() + self.files.values().map(String::len).sum::<usize>();
+        if size > limits.max_source_bytes {
+            return Err(Rejected::new(
+                FailureReason::SourceTooLarge,
+                format!(
+                    "package sources are {size} bytes; the limit is {} bytes",
+                    limits.max_source_bytes
+                ),
+            )
+            .into());
+        }
+        let with_nul = std::iter::once((ENTRY_FILE, &self.source))
+            .chain(
+                self.files
+                    .iter()
+                    .map(|(path, contents)| (path.as_str(), contents)),
+            )
+            .find(|(path, contents)| path.contains('\0') || contents.contains('\0'));
+        if let Some((path, _)) = with_nul {
+            return Err(Rejected::new(
+                FailureReason::NulByte,
+                format!("source file {path:?} contains a NUL byte"),
+            )
+            .into());
+        }
+
+        let mut files = BTreeMap::new();
+        for (path, contents) in &self.files {
+            let relative = package_source_path(path)?;
+            anyhow::ensure!(
+                files.insert(relative, contents.as_str()).is_none(),
+                "duplicate source file: {path}"
+            );
+        }
+        let entry = PathBuf::from(ENTRY_FILE);
+        match (files.contains_key(&entry), self.source.is_empty()) {
+            (true, false) => {
+                anyhow::bail!("`source` and `files` both provide {ENTRY_FILE}")
+            }
+            (false, _) => {
+                files.insert(entry, self.source.as_str());
+            }
+            (true, true) => {}
+        }
+        Ok(files)
+    }
+}
+
+/// Why a request was refused rather than compiled, for clients to act on.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureReason {
+    /// The build queue is full; see `retry_after`.
+    Busy,
+    /// The request body exceeds `limits.max_body_bytes`.
+    BodyTooLarge,
+    SourceTooLarge,
+    TooManyFiles,
+    NulByte,
+}
+
+impl FailureReason {
+    fn status(self) -> StatusCode {
+        match self {
+            Self::Busy => StatusCode::SERVICE_UNAVAILABLE,
+            Self::BodyTooLarge | Self::SourceTooLarge | Self::TooManyFiles => {
+                StatusCode::PAYLOAD_TOO_LARGE
+            }
+            Self::NulByte => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+/// JSON body extractor for the compile routes. Bodies over
+/// `limits.max_body_bytes` get the standard failure body with
+/// `reason: "body_too_large"` instead of axum's plain-text rejection.
+pub struct RequestJson<T>(pub T);
+
+impl<T, S> FromRequest<S> for RequestJson<T>
+where
+    Json<T>: FromRequest<S, Rejection = JsonRejection>,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        match Json::<T>::from_request(req, state).await {
+            Ok(Json(value)) => Ok(Self(value)),
+            Err(JsonRejection::BytesRejection(rejection))
+                if rejection.status() == StatusCode::PAYLOAD_TOO_LARGE =>
+            {
+                let reason = FailureReason::BodyTooLarge;
+                let response = CompileResponse {
+                    reason: Some(reason),
+                    ..CompileResponse::err("request body exceeds the size limit".to_owned())
+                };
+                Err(refused(reason, None, Json(response).into_response()))
+            }
+            Err(rejection) => Err(rejection.into_response()),
+        }
+    }
+}
+
+/// A request refused before any build work.
+#[derive(Debug)]
+struct Rejected {
+    reason: FailureReason,
+    message: String,
+}
+
+impl Rejected {
+    fn new(reason: FailureReason, message: String) -> Self {
+        Self { reason, message }
+    }
+}
+
+impl std::fmt::Display for Rejected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Rejected {}
+
+/// Validate a client-supplied module path: relative, free of `..` and other
+/// special components, and naming a `.rr` file.
+fn package_source_path(path: &str) -> Result<PathBuf> {
+    let relative = PathBuf::from(path);
+    anyhow::ensure!(
+        !path.is_empty()
+            && relative
+                .components()
+                .all(|component| matches!(component, Component::Normal(_))),
+        "invalid source path: {path:?}"
+    );
+    anyhow::ensure!(
+        relative.extension() == Some(OsStr::new("rr")),
+        "source path must end in .rr: {path:?}"
+    );
+    Ok(relative)
+}
+
 #[derive(Serialize)]
 pub struct CompileResponse {
     pub success: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub output: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub wasm: Option<String>,
+    /// Stripped run-mode module, base64-encoded in JSON.
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_base64"
+    )]
+    pub wasm: Option<Vec<u8>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<String>,
+    /// Structured diagnostics parsed from the failing tool's output.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub diagnostics: Vec<Diagnostic>,
+    /// Tool output that could not be parsed into `diagnostics`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw: Option<String>,
+    /// The artifact was served from the compile cache.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub cached: bool,
+    /// Size breakdown of the run-mode module. Not sent with raw
+    /// `application/wasm` responses.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size_report: Option<SizeReport>,
+    /// Imports and exports of the run-mode module.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub module: Option<ModuleInterface>,
+    /// Reasons the run-mode module may fail in the browser runtime.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<FailureReason>,
+    /// Seconds to wait before retrying; set when the build queue was full.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_after: Option<u64>,
 }
 
 impl CompileResponse {
@@ -58,6 +332,14 @@ impl CompileResponse {
             output: Some(output),
             wasm: None,
             error: None,
+            diagnostics: Vec::new(),
+            raw: None,
+            cached: false,
+            size_report: None,
+            module: None,
+            warnings: Vec::new(),
+            reason: None,
+            retry_after: None,
         }
     }
 
@@ -65,8 +347,16 @@ impl CompileResponse {
         Self {
             success: true,
             output: None,
-            wasm: Some(B64.encode(bytes)),
+            wasm: Some(bytes),
             error: None,
+            diagnostics: Vec::new(),
+            raw: None,
+            cached: false,
+            size_report: None,
+            module: None,
+            warnings: Vec::new(),
+            reason: None,
+            retry_after: None,
         }
     }
 
@@ -76,85 +366,578 @@ impl CompileResponse {
             output: None,
             wasm: None,
             error: Some(msg.into()),
+            diagnostics: Vec::new(),
+            raw: None,
+            cached: false,
+            size_report: None,
+            module: None,
+            warnings: Vec::new(),
+            reason: None,
+            retry_after: None,
+        }
+    }
+
+    fn failure(error: &anyhow::Error) -> Self {
+        let mut response = Self::err(error.to_string());
+        if let Some(failure) = error.downcast_ref::<ToolFailure>() {
+            let parsed = diagnostics::parse(&failure.output);
+            response.diagnostics = parsed.diagnostics;
+            response.raw = (!parsed.raw.is_empty()).then_some(parsed.raw);
+        }
+        if error.downcast_ref::<Busy>().is_some() {
+            response.reason = Some(FailureReason::Busy);
+            response.retry_after = Some(RETRY_AFTER_SECS);
+        }
+        if let Some(rejected) = error.downcast_ref::<Rejected>() {
+            response.reason = Some(rejected.reason);
         }
+        response
+    }
+}
+
+fn serialize_base64<S: Serializer>(
+    bytes: &Option<Vec<u8>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match bytes {
+        Some(bytes) => serializer.serialize_str(&B64.encode(bytes)),
+        None => serializer.serialize_none(),
     }
 }
 
 pub async fn handle(
-    State(cfg): State<Arc<Config>>,
-    Json(req): Json<CompileRequest>,
-) -> Json<CompileResponse> {
-    let opt = match req.opt.as_str() {
-        "none" | "default" | "size" | "aggressive" => req.opt.as_str(),
-        _ => "none",
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    key: ApiKey,
+    headers: HeaderMap,
+    RequestJson(req): RequestJson<CompileRequest>,
+) -> Response {
+    let budget = Budget::for_mode(&req.mode);
+    let api_key = key.as_ref().map(|Extension(key)| key.as_ref());
+    if let Err(limited) = state.limits.check(peer, &headers, api_key, budget, 1) {
+        return vary_on_accept(limited.into_response());
+    }
+    let timeout = auth::request_timeout(&state.config, &key);
+    let mut response = compile(&state, &req, timeout, &Progress::disabled()).await;
+    if let Some(reason) = response.reason {
+        let body = Json(&response).into_response();
+        return vary_on_accept(refused(reason, response.retry_after, body));
+    }
+    let body = match (accepts_wasm(&headers), response.wasm.take()) {
+        (true, Some(wasm)) => {
+            let mut body = wasm.into_response();
+            let headers = body.headers_mut();
+            headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(WASM_MIME));
+            if response.cached {
+                headers.insert("x-compile-cached", HeaderValue::from_static("true"));
+            }
+            if !response.warnings.is_empty() {
+                let warnings = serde_json::to_string(&response.warnings)
+                    .ok()
+                    .and_then(|json| HeaderValue::from_str(&json).ok());
+                if let Some(warnings) = warnings {
+                    headers.insert("x-compile-warnings", warnings);
+                }
+            }
+            body
+        }
+        (_, wasm) => {
+            response.wasm = wasm;
+            Json(response).into_response()
+        }
+    };
+    vary_on_accept(body)
+}
+
+/// Give `body` the status for `reason`, with `Retry-After` when the client
+/// should try again.
+pub fn refused(reason: FailureReason, retry_after: Option<u64>, mut body: Response) -> Response {
+    *body.status_mut() = reason.status();
+    if let Some(retry_after) = retry_after {
+        body.headers_mut()
+            .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+    }
+    body
+}
+
+/// Whether `Accept` lists `application/wasm` with a nonzero quality.
+fn accepts_wasm(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|media| {
+            let mut parts = media.split(';').map(str::trim);
+            parts.next() == Some(WASM_MIME)
+                && parts
+                    .filter_map(|param| param.strip_prefix("q="))
+                    .all(|q| q.parse::<f32>().is_ok_and(|q| q > 0.0))
+        })
+}
+
+fn vary_on_accept(mut response: Response) -> Response {
+    response
+        .headers_mut()
+        .insert(header::VARY, HeaderValue::from_static("accept"));
+    response
+}
+
+/// Streaming variant of [`handle`]. Emits `phase` and `output` events while
+/// the pipeline runs and a final `result` event carrying the same payload as
+/// `/api/compile`. Disconnecting cancels the compile.
+///
+/// Packages over the limits are refused with the same status as [`handle`]
+/// before the stream opens. A full build queue is only found once the stream
+/// has answered `200`, so it arrives as a `result` with `reason: "busy"`.
+pub async fn handle_stream(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    key: ApiKey,
+    headers: HeaderMap,
+    RequestJson(req): RequestJson<CompileRequest>,
+) -> Response {
+    let budget = Budget::for_mode(&req.mode);
+    let api_key = key.as_ref().map(|Extension(key)| key.as_ref());
+    if let Err(limited) = state.limits.check(peer, &headers, api_key, budget, 1) {
+        return limited.into_response();
+    }
+    if let Err(error) = req.package_files(&state.config.limits) {
+        // Other invalid packages fail in the stream's `result` event.
+        let response = CompileResponse::failure(&error);
+        if let Some(reason) = response.reason {
+            let body = Json(&response).into_response();
+            return refused(reason, response.retry_after, body);
+        }
+    }
+    let timeout = auth::request_timeout(&state.config, &key);
+    stream_events(move |progress| async move { compile(&state, &req, timeout, &progress).await })
+}
+
+/// Run `compile` in the background, streaming its progress events, then
+/// `done` and its `result`. Dropping the stream cancels the compile.
+fn stream_events<F>(compile: impl FnOnce(Progress) -> F) -> Response
+where
+    F: Future<Output = CompileResponse> + Send + 'static,
+{
+    let (events_tx, events_rx) = mpsc::unbounded_channel();
+    let (result_tx, result_rx) = mpsc::unbounded_channel();
+    let progress = Progress::new(events_tx.clone());
+    let run = compile(progress.clone());
+    tokio::spawn(
+        async move {
+            tokio::select! {
+                response = run => {
+                    progress.phase(Phase::Done);
+                    let _ = result_tx.send(response);
+                }
+                () = events_tx.closed() => {}
+            }
+        }
+        .in_current_span(),
+    );
+
+    let events = UnboundedReceiverStream::new(events_rx).map(|event| event.to_sse());
+    let result = UnboundedReceiverStream::new(result_rx).map(|response| {
+        Event::default()
+            .event("result")
+            .json_data(response)
+            .expect("compile responses serialize to JSON")
+    });
+    Sse::new(events.chain(result).map(Ok::<_, Infallible>))
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+/// Compile `req`, giving up after `timeout`.
+pub async fn compile(
+    state: &AppState,
+    req: &CompileRequest,
+    timeout: Duration,
+    progress: &Progress,
+) -> CompileResponse {
+    let started = Instant::now();
+    let (response, outcome) =
+        match tokio::time::timeout(timeout, compile_cached(state, req, progress)).await {
+            Ok(Ok(mut response)) => {
+                check_module(&state.config, &mut response);
+                let outcome = if response.cached { "cached" } else { "success" };
+                (response, outcome)
+            }
+            Ok(Err(error)) => {
+                let response = CompileResponse::failure(&error);
+                let outcome = match response.reason {
+                    Some(FailureReason::Busy) => "busy",
+                    Some(_) => "rejected",
+                    None => "failure",
+                };
+                (response, outcome)
+            }
+            Err(_) => {
+                METRICS.timeouts.with_label_values(&["request"]).inc();
+                let response = CompileResponse::err(format!(
+                    "compile did not finish within {}s",
+                    timeout.as_secs()
+                ));
+                (response, "timeout")
+            }
+        };
+    record_metrics(&state.config, req, &response, outcome, started.elapsed());
+    response
+}
+
+fn record_metrics(
+    cfg: &Config,
+    req: &CompileRequest,
+    response: &CompileResponse,
+    outcome: &str,
+    elapsed: Duration,
+) {
+    // Request values are client-controlled; keep label sets bounded.
+    let known_mode = RUN_MODES.contains(&req.mode.as_str())
+        || cfg
+            .compiler
+            .emit_modes
+            .iter()
+            .any(|mode| mode.name == req.mode);
+    let mode = if known_mode {
+        req.mode.as_str()
+    } else {
+        "unknown"
+    };
+    let opt = match req.options.opt.as_str() {
+        opt if OPT_LEVELS.contains(&opt) => opt,
+        _ => "other",
+    };
+    METRICS
+        .requests
+        .with_label_values(&[mode, opt, outcome])
+        .inc();
+    METRICS
+        .request_duration
+        .with_label_values(&[mode, outcome])
+        .observe(elapsed.as_secs_f64());
+    let size = match (&response.wasm, &response.output) {
+        (Some(wasm), _) => Some(wasm.len()),
+        (None, Some(output)) if response.success => Some(output.len()),
+        _ => None,
+    };
+    if let Some(size) = size {
+        METRICS
+            .output_bytes
+            .with_label_values(&[mode])
+            .observe(size as f64);
+    }
+}
+
+/// List the run-mode module's imports and exports and warn about those the
+/// browser runtime cannot satisfy. Runs on cached results too, so warnings
+/// follow the current `runtime.supported_imports`.
+fn check_module(cfg: &Config, response: &mut CompileResponse) {
+    let Some(wasm) = &response.wasm else {
+        return;
+    };
+    match module_interface::inspect(wasm) {
+        Ok(interface) => {
+            response.warnings = interface.warnings(&cfg.runtime.supported_imports);
+            response.module = Some(interface);
+        }
+        Err(error) => tracing::warn!("cannot inspect WASM module: {error:#}"),
+    }
+}
+
+async fn compile_cached(
+    state: &AppState,
+    req: &CompileRequest,
+    progress: &Progress,
+) -> Result<CompileResponse> {
+    let cfg = &state.config;
+    let toolchain = state.toolchain(req.options.toolchain.as_deref())?;
+    let opt = match req.options.opt.as_str() {
+        opt if toolchain.opt_levels.iter().any(|level| level == opt) => opt,
+        _ => &toolchain.opt_levels[0],
     };
-    let result = match req.mode.as_str() {
-        "llvm-ir" | "asm" | "mlir" => compile_text(&cfg, &req, opt).await,
-        "run" => compile_run(&cfg, &req, opt).await,
-        other => Err(anyhow::anyhow!("unknown mode: {other}")),
+    let mode = req.mode.as_str();
+    anyhow::ensure!(
+        toolchain.modes.iter().any(|supported| supported == mode),
+        "unknown mode: {mode}"
+    );
+    let text_mode = toolchain.text_modes.iter().find(|text| text.name == mode);
+    let files = req.package_files(&cfg.limits)?;
+
+    let key = cache_key(
+        cfg,
+        &toolchain.config,
+        &files,
+        mode,
+        text_mode,
+        opt,
+        &req.options,
+    )?;
+    let report_key = key.derived("size-report");
+    if let Some(bytes) = state.cache.get(&key).await {
+        let mut artifact = Artifact::from_cached(mode, bytes)?;
+        if let Artifact::Wasm(run) = &mut artifact {
+            run.size_report = state
+                .cache
+                .get(&report_key)
+                .await
+                .and_then(|json| serde_json::from_slice(&json).ok());
+        }
+        let mut response = artifact.into_response();
+        response.cached = true;
+        return Ok(response);
+    }
+
+    let artifact = match mode {
+        "run" => {
+            Artifact::Wasm(compile_run(cfg, toolchain, req, &files, opt, true, progress).await?)
+        }
+        "wat" => {
+            let run = compile_run(cfg, toolchain, req, &files, opt, false, progress).await?;
+            Artifact::Text(disassemble(run.wasm).await?)
+        }
+        _ => {
+            let text_mode = text_mode.context("mode has no rrc emit kind")?;
+            Artifact::Text(
+                compile_text(cfg, toolchain, text_mode, req, &files, opt, progress).await?,
+            )
+        }
     };
+    state.cache.insert(&key, artifact.as_bytes().to_vec()).await;
+    if let Artifact::Wasm(RunOutput {
+        size_report: Some(report),
+        ..
+    }) = &artifact
+    {
+        state
+            .cache
+            .insert(&report_key, serde_json::to_vec(report)?)
+            .await;
+    }
+    Ok(artifact.into_response())
+}
+
+/// Successful pipeline output, as stored in the compile cache.
+enum Artifact {
+    Wasm(RunOutput),
+    Text(String),
+}
+
+/// Stripped run-mode module. The size report is cached under a derived key
+/// and may be missing after eviction.
+struct RunOutput {
+    wasm: Vec<u8>,
+    size_report: Option<SizeReport>,
+}
+
+impl Artifact {
+    fn from_cached(mode: &str, bytes: Vec<u8>) -> Result<Self> {
+        Ok(match mode {
+            "run" => Self::Wasm(RunOutput {
+                wasm: bytes,
+                size_report: None,
+            }),
+            _ => Self::Text(String::from_utf8(bytes).context("cached text output is not UTF-8")?),
+        })
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        match self {
+            Self::Wasm(run) => &run.wasm,
+            Self::Text(text) => text.as_bytes(),
+        }
+    }
+
+    fn into_response(self) -> CompileResponse {
+        match self {
+            Self::Wasm(run) => CompileResponse {
+                size_report: run.size_report,
+                ..CompileResponse::wasm(run.wasm)
+            },
+            Self::Text(text) => CompileResponse::text(text),
+        }
+    }
+}
+
+/// Hash everything that can change a compile result, including the identity
+/// of every toolchain binary involved.
+fn cache_key(
+    cfg: &Config,
+    tc: &ToolchainConfig,
+    files: &BTreeMap<PathBuf, &str>,
+    mode: &str,
+    text_mode: Option<&EmitModeConfig>,
+    opt: &str,
+    options: &CompileOptions,
+) -> Result<CacheKey> {
+    let mut key = CacheKey::builder().field(mode);
+    if let Some(text_mode) = text_mode {
+        key = key
+            .field(&text_mode.emit)
+            .field((text_mode.args.len() as u64).to_le_bytes());
+        for arg in &text_mode.args {
+            key = key.field(arg);
+        }
+    }
+    key = key
+        .field(opt)
+        .field([u8::from(options.reuse_across_call)])
+        // Text modes build without debug info either way.
+        .field([u8::from(options.debug && text_mode.is_none())])
+        .field((files.len() as u64).to_le_bytes());
+    for (path, contents) in files {
+        key = key
+            .field(path.as_os_str().as_encoded_bytes())
+            .field(contents);
+    }
+    for tool in [
+        tc.rrc_path.clone(),
+        tc.rene_path.clone(),
+        cfg.compiler.llvm_strip_path.clone(),
+        resolve_program("rustc", tc.rustc_path.as_deref())?,
+        resolve_program("cargo", tc.cargo_path.as_deref())?,
+    ] {
+        key = cache::fingerprint_file(key, &tool)?;
+    }
+    Ok(key.finish())
+}
 
-    Json(match result {
-        Ok(response) => response,
-        Err(error) => CompileResponse::err(error.to_string()),
-    })
+/// WebAssembly text of `wasm`, printed on the blocking pool since large
+/// modules take a while.
+pub async fn disassemble(wasm: Vec<u8>) -> Result<String> {
+    tokio::task::spawn_blocking(move || wasmprinter::print_bytes(&wasm))
+        .await?
+        .context("cannot disassemble the WASM module")
 }
 
-async fn compile_run(cfg: &Config, req: &CompileRequest, opt: &str) -> Result<CompileResponse> {
-    // Rene uses an exclusive redb database in the shared build directory.
-    let rene_guard = RENE_LOCK.lock().await;
-    let rene_cli = detect_rene_cli(cfg).await?;
-    let package = PackageDir::new(cfg, &req.source, opt, req.reuse_across_call, true, rene_cli)?;
-    let output = run_rene(cfg, &package, true, rene_cli).await?;
-    drop(rene_guard);
+/// Build, strip, and, with `report_size`, measure the run-mode module.
+async fn compile_run(
+    cfg: &Config,
+    toolchain: &Toolchain,
+    req: &CompileRequest,
+    files: &BTreeMap<PathBuf, &str>,
+    opt: &str,
+    report_size: bool,
+    progress: &Progress,
+) -> Result<RunOutput> {
+    let tc = &toolchain.config;
+    progress.phase(Phase::Queued);
+    let slot = toolchain.builds.acquire(progress).await?;
+    let package = PackageDir::new(
+        tc,
+        files,
+        opt,
+        req.options.reuse_across_call,
+        req.options.debug,
+        true,
+        toolchain.rene_cli,
+    )?;
+    progress.phase(Phase::BakingRuntime);
+    let output = run_rene(
+        cfg,
+        tc,
+        slot.dir(),
+        &package,
+        true,
+        toolchain.rene_cli,
+        progress,
+    )
+    .await?;
+    slot.mark_baked();
+    drop(slot);
     let artifact = output
         .lines()
-        .filter(|line| !line.trim().is_empty())
-        .next_back()
+        .rfind(|line| !line.trim().is_empty())
         .map(PathBuf::from)
         .context("rene returned no WASM artifact path")?;
-    ensure_inside(&artifact, &cfg.compiler.build_dir)
-        .context("rene returned an artifact outside compiler.build_dir")?;
+    ensure_inside(&artifact, &tc.build_dir)
+        .context("rene returned an artifact outside the toolchain build_dir")?;
 
+    progress.phase(Phase::Stripping);
     let stripped = package.root().join("playground.stripped.wasm");
+    // Debug builds drop only DWARF; the `name` section is what the browser
+    // uses to symbolize traps.
+    let strip = if req.options.debug {
+        "--strip-debug"
+    } else {
+        "--strip-all"
+    };
     let args = [
-        OsStr::new("--strip-all"),
+        OsStr::new(strip),
         OsStr::new("-o"),
         stripped.as_os_str(),
         artifact.as_os_str(),
     ];
     let strip_out = run_sandboxed(
         cfg,
+        tc,
+        "llvm-strip",
         &cfg.compiler.llvm_strip_path,
+        Some(package.root()),
         args,
         Duration::from_secs(cfg.compiler.compile_timeout_secs),
+        progress,
     )
     .await
     .context("failed to strip WASM")?;
-    ensure_success("llvm-strip", &strip_out)?;
+    ensure_success(
+        "llvm-strip",
+        &strip_out,
+        Some(package.root()),
+        &tc.build_dir,
+    )?;
 
     let wasm = std::fs::read(&stripped)
         .with_context(|| format!("stripped WASM not found at {}", stripped.display()))?;
-    Ok(CompileResponse::wasm(wasm))
+    let size_report = report_size
+        .then(|| {
+            std::fs::read(&artifact)
+                .map_err(anyhow::Error::from)
+                .and_then(|unstripped| size_report::report(&wasm, &unstripped, package.name()))
+                .inspect_err(|error| tracing::warn!("cannot measure WASM module: {error:#}"))
+                .ok()
+        })
+        .flatten();
+    Ok(RunOutput { wasm, size_report })
 }
 
-async fn compile_text(cfg: &Config, req: &CompileRequest, opt: &str) -> Result<CompileResponse> {
-    // Rene uses an exclusive redb database in the shared build directory.
-    let rene_guard = RENE_LOCK.lock().await;
-    let rene_cli = detect_rene_cli(cfg).await?;
+async fn compile_text(
+    cfg: &Config,
+    toolchain: &Toolchain,
+    text_mode: &EmitModeConfig,
+    req: &CompileRequest,
+    files: &BTreeMap<PathBuf, &str>,
+    opt: &str,
+    progress: &Progress,
+) -> Result<String> {
+    let tc = &toolchain.config;
+    progress.phase(Phase::Queued);
+    let slot = toolchain.builds.acquire(progress).await?;
     let package = PackageDir::new(
-        cfg,
-        &req.source,
+        tc,
+        files,
         opt,
-        req.reuse_across_call,
+        req.options.reuse_across_call,
         false,
-        rene_cli,
+        false,
+        toolchain.rene_cli,
     )?;
 
     // A target-less Rene build bakes/reuses reussir-rt and prints exactly the
     // directories rrc needs for PolyFFI compilation, one per stdout line.
-    let libdir_output = run_rene(cfg, &package, false, rene_cli).await?;
-    drop(rene_guard);
+    progress.phase(Phase::BakingRuntime);
+    let libdir_output = run_rene(
+        cfg,
+        tc,
+        slot.dir(),
+        &package,
+        false,
+        toolchain.rene_cli,
+        progress,
+    )
+    .await?;
+    drop(slot);
     let libdirs: Vec<PathBuf> = libdir_output
         .lines()
         .map(str::trim)
@@ -166,13 +949,9 @@ async fn compile_text(cfg: &Config, req: &CompileRequest, opt: &str) -> Result<C
         "rene returned no PolyFFI library directories"
     );
 
-    let (emit, extension) = match req.mode.as_str() {
-        "llvm-ir" => ("llvm-ir", "ll"),
-        "asm" => ("asm", "s"),
-        "mlir" => ("mlir", "mlir"),
-        _ => unreachable!(),
-    };
-    let output_path = package.root().join(format!("output.{extension}"));
+    let output_path = package
+        .root()
+        .join(format!("output.{}", text_mode.extension));
     let mut args: Vec<OsString> = vec![
         package.source().as_os_str().to_owned(),
         OsString::from("--package-name"),
@@ -180,33 +959,73 @@ async fn compile_text(cfg: &Config, req: &CompileRequest, opt: &str) -> Result<C
         OsString::from("-o"),
         output_path.as_os_str().to_owned(),
         OsString::from("--emit"),
-        OsString::from(emit),
+        OsString::from(&text_mode.emit),
         OsString::from("-O"),
         OsString::from(opt),
         OsString::from("--target-triple"),
         OsString::from(TARGET),
     ];
-    if req.reuse_across_call {
+    if req.options.reuse_across_call {
         args.push(OsString::from("--reuse-across-call"));
     }
     for libdir in &libdirs {
         args.push(OsString::from("--polyffi-libdir"));
         args.push(libdir.as_os_str().to_owned());
     }
+    args.extend(text_mode.args.iter().map(OsString::from));
 
+    progress.phase(Phase::Compiling);
     let out = run_sandboxed(
         cfg,
-        &cfg.compiler.rrc_path,
+        tc,
+        "rrc",
+        &tc.rrc_path,
+        Some(package.root()),
         args,
         Duration::from_secs(cfg.compiler.compile_timeout_secs),
+        progress,
     )
     .await
     .context("failed to run rrc")?;
-    ensure_success("rrc", &out)?;
+    ensure_success("rrc", &out, Some(package.root()), &tc.build_dir)?;
+
+    std::fs::read_to_string(&output_path)
+        .with_context(|| format!("rrc output not found at {}", output_path.display()))
+}
 
-    let text = std::fs::read_to_string(&output_path)
-        .with_context(|| format!("rrc output not found at {}", output_path.display()))?;
-    Ok(CompileResponse::text(text))
+/// Bake the runtime in `slot` for `opt` with a target-less and an
+/// executable Rene build, the two builds requests start with.
+pub async fn bake(
+    cfg: &Config,
+    toolchain: &Toolchain,
+    slot: &BuildSlot<'_>,
+    opt: &str,
+    progress: &Progress,
+) -> Result<()> {
+    let files = BTreeMap::from([(PathBuf::from(ENTRY_FILE), WARM_UP_SOURCE)]);
+    for executable in [false, true] {
+        let package = PackageDir::new(
+            &toolchain.config,
+            &files,
+            opt,
+            false,
+            false,
+            executable,
+            toolchain.rene_cli,
+        )?;
+        run_rene(
+            cfg,
+            &toolchain.config,
+            slot.dir(),
+            &package,
+            executable,
+            toolchain.rene_cli,
+            progress,
+        )
+        .await?;
+    }
+    slot.mark_baked();
+    Ok(())
 }
 
 struct PackageDir {
@@ -219,14 +1038,15 @@ struct PackageDir {
 
 impl PackageDir {
     fn new(
-        cfg: &Config,
-        source: &str,
+        tc: &ToolchainConfig,
+        files: &BTreeMap<PathBuf, &str>,
         opt: &str,
         reuse_across_call: bool,
+        debug: bool,
         executable: bool,
         rene_cli: ReneCli,
     ) -> Result<Self> {
-        let requests = cfg.compiler.build_dir.join("requests");
+        let requests = tc.build_dir.join("requests");
         std::fs::create_dir_all(&requests)
             .with_context(|| format!("cannot create {}", requests.display()))?;
         let temp = TempBuilder::new()
@@ -242,8 +1062,16 @@ impl PackageDir {
         let name = format!("playground_{suffix}");
         let source_dir = root.join("src");
         std::fs::create_dir(&source_dir).context("failed to create package source directory")?;
-        let source_path = source_dir.join("lib.rr");
-        std::fs::write(&source_path, source).context("failed to write Reussir source")?;
+        for (relative, contents) in files {
+            let path = source_dir.join(relative);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)
+                    .with_context(|| format!("failed to create {}", parent.display()))?;
+            }
+            std::fs::write(&path, contents)
+                .with_context(|| format!("failed to write src/{}", relative.display()))?;
+        }
+        let source_path = source_dir.join(ENTRY_FILE);
 
         let targets = if executable {
             format!("\n  targets.{name} = {{ kind = 'executable }},")
@@ -259,7 +1087,7 @@ impl PackageDir {
   package = {{ name = "{name}", version = "0.1.0" }},{targets}
   profiles.{PROFILE} = {{
     opt = "{opt}",
-    debug = false,
+    debug = {debug},
     {target_profile}
     reuse_across_call = {reuse_across_call},
   }},
@@ -291,44 +1119,21 @@ impl PackageDir {
     }
 }
 
-#[derive(Clone, Copy)]
-enum ReneCli {
-    /// Nightlies before machine targets moved to `--target <TRIPLE>`.
-    Legacy,
-    /// Current Rene: `--target <TRIPLE>` selects the machine target.
-    Modern,
-}
-
-async fn detect_rene_cli(cfg: &Config) -> Result<ReneCli> {
-    let out = run_sandboxed(
-        cfg,
-        &cfg.compiler.rene_path,
-        ["build", "--help"],
-        Duration::from_secs(cfg.compiler.compile_timeout_secs),
-    )
-    .await
-    .context("failed to inspect rene CLI")?;
-    ensure_success("rene --help", &out)?;
-    let help = String::from_utf8_lossy(&out.stdout);
-    Ok(if help.contains("--bin <") {
-        ReneCli::Modern
-    } else {
-        ReneCli::Legacy
-    })
-}
-
 async fn run_rene(
     cfg: &Config,
+    tc: &ToolchainConfig,
+    build_dir: &Path,
     package: &PackageDir,
     executable: bool,
     rene_cli: ReneCli,
+    progress: &Progress,
 ) -> Result<String> {
     let mut args: Vec<OsString> = vec![
         OsString::from("build"),
         OsString::from("--manifest-path"),
         package.manifest.as_os_str().to_owned(),
         OsString::from("--build-dir"),
-        cfg.compiler.build_dir.as_os_str().to_owned(),
+        build_dir.as_os_str().to_owned(),
         OsString::from("--profile"),
         OsString::from(PROFILE),
     ];
@@ -345,62 +1150,136 @@ async fn run_rene(
     }
     let out = run_sandboxed(
         cfg,
-        &cfg.compiler.rene_path,
+        tc,
+        "rene",
+        &tc.rene_path,
+        Some(package.root()),
         args,
         Duration::from_secs(cfg.compiler.build_timeout_secs),
+        progress,
     )
     .await
     .context("failed to run rene")?;
-    ensure_success("rene", &out)?;
+    ensure_success("rene", &out, Some(package.root()), &tc.build_dir)?;
     Ok(String::from_utf8_lossy(&out.stdout).into_owned())
 }
 
-async fn run_sandboxed<I, S>(
+/// Run `program` in the sandbox. `tool` names it in progress events and
+/// metrics; output lines sent to `progress` hide paths under `package_root`
+/// and `tc.build_dir`.
+#[allow(clippy::too_many_arguments)]
+pub async fn run_sandboxed<I, S>(
     cfg: &Config,
+    tc: &ToolchainConfig,
+    tool: &str,
     program: &Path,
+    package_root: Option<&Path>,
     args: I,
     timeout: Duration,
+    progress: &Progress,
 ) -> Result<std::process::Output>
 where
     I: IntoIterator<Item = S>,
     S: AsRef<OsStr>,
 {
-    let extra_ro = tool_ro_paths(cfg);
+    let extra_ro = tool_ro_paths(cfg, tc);
     let extra_ro_refs: Vec<&Path> = extra_ro.iter().map(PathBuf::as_path).collect();
-    let mut command: Command = crate::sandbox::wrap(
-        &cfg.sandbox,
-        program,
-        args,
-        &cfg.compiler.build_dir,
-        &extra_ro_refs,
-    )?;
+    let mut command: Command =
+        crate::sandbox::wrap(&cfg.sandbox, program, args, &tc.build_dir, &extra_ro_refs)?;
     command
-        .env("CARGO_HOME", cfg.compiler.cargo_home())
-        .env("TMPDIR", cfg.compiler.build_dir.join("tmp"))
+        .env("CARGO_HOME", tc.cargo_home())
+        .env("TMPDIR", tc.build_dir.join("tmp"))
         .env(
             "REUSSIR_RUSTC",
-            resolve_program("rustc", cfg.compiler.rustc_path.as_deref())?,
+            resolve_program("rustc", tc.rustc_path.as_deref())?,
         )
         .env(
             "REUSSIR_CARGO",
-            resolve_program("cargo", cfg.compiler.cargo_path.as_deref())?,
+            resolve_program("cargo", tc.cargo_path.as_deref())?,
         )
-        .env("PATH", tool_path(cfg)?)
-        .current_dir(&cfg.compiler.build_dir)
+        .env("PATH", tool_path(tc)?)
+        .current_dir(&tc.build_dir)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
         .kill_on_drop(true);
-    tokio::time::timeout(timeout, command.output())
-        .await
-        .with_context(|| {
-            format!(
-                "{} timed out after {}s",
-                program.display(),
-                timeout.as_secs()
-            )
-        })?
-        .with_context(|| format!("failed to spawn {}", program.display()))
+    let run = async {
+        let mut child = command
+            .spawn()
+            .with_context(|| format!("failed to spawn {}", program.display()))?;
+        let stdout = child.stdout.take().context("child stdout is not piped")?;
+        let stderr = child.stderr.take().context("child stderr is not piped")?;
+        let (stdout, stderr, status) = tokio::join!(
+            collect_lines(
+                stdout,
+                tool,
+                Stream::Stdout,
+                package_root,
+                &tc.build_dir,
+                progress
+            ),
+            collect_lines(
+                stderr,
+                tool,
+                Stream::Stderr,
+                package_root,
+                &tc.build_dir,
+                progress
+            ),
+            child.wait(),
+        );
+        Ok(std::process::Output {
+            status: status.with_context(|| format!("failed to wait for {}", program.display()))?,
+            stdout: stdout
+                .with_context(|| format!("failed to read {} stdout", program.display()))?,
+            stderr: stderr
+                .with_context(|| format!("failed to read {} stderr", program.display()))?,
+        })
+    };
+    let started = Instant::now();
+    let result = tokio::time::timeout(timeout, run).await;
+    METRICS
+        .tool_duration
+        .with_label_values(&[tool])
+        .observe(started.elapsed().as_secs_f64());
+    if result.is_err() {
+        METRICS.timeouts.with_label_values(&["tool"]).inc();
+    }
+    result.with_context(|| {
+        format!(
+            "{} timed out after {}s",
+            program.display(),
+            timeout.as_secs()
+        )
+    })?
+}
+
+/// Read a child pipe to the end, forwarding each line to `progress` while
+/// keeping the exact bytes for the caller.
+/// Read `pipe` to the end, reporting each line to `progress` with server
+/// paths hidden. The returned bytes are unchanged.
+async fn collect_lines(
+    pipe: impl AsyncRead + Unpin,
+    tool: &str,
+    stream: Stream,
+    package_root: Option<&Path>,
+    build_dir: &Path,
+    progress: &Progress,
+) -> std::io::Result<Vec<u8>> {
+    let mut reader = BufReader::new(pipe);
+    let mut bytes = Vec::new();
+    loop {
+        let start = bytes.len();
+        if reader.read_until(b'\n', &mut bytes).await? == 0 {
+            return Ok(bytes);
+        }
+        let line = String::from_utf8_lossy(&bytes[start..]);
+        let line = diagnostics::hide_server_paths(&line, package_root, build_dir);
+        progress.output(tool, stream, line.trim_end_matches(['\n', '\r']));
+    }
 }
 
-fn resolve_program(name: &str, configured: Option<&Path>) -> Result<PathBuf> {
+pub fn resolve_program(name: &str, configured: Option<&Path>) -> Result<PathBuf> {
     if let Some(path) = configured {
         anyhow::ensure!(
             path.is_file(),
@@ -430,9 +1309,9 @@ fn absolute_path(path: &Path) -> Result<PathBuf> {
         .join(path))
 }
 
-fn tool_path(cfg: &Config) -> Result<OsString> {
+fn tool_path(tc: &ToolchainConfig) -> Result<OsString> {
     let mut entries = Vec::new();
-    for program in [&cfg.compiler.rrc_path, &cfg.compiler.rene_path] {
+    for program in [&tc.rrc_path, &tc.rene_path] {
         if let Some(parent) = program.parent() {
             entries.push(parent.to_owned());
         }
@@ -443,13 +1322,9 @@ fn tool_path(cfg: &Config) -> Result<OsString> {
     std::env::join_paths(entries).context("cannot construct compiler PATH")
 }
 
-fn tool_ro_paths(cfg: &Config) -> Vec<PathBuf> {
+fn tool_ro_paths(cfg: &Config, tc: &ToolchainConfig) -> Vec<PathBuf> {
     let mut paths = cfg.compiler.toolchain_ro_paths.clone();
-    for program in [
-        &cfg.compiler.rrc_path,
-        &cfg.compiler.rene_path,
-        &cfg.compiler.llvm_strip_path,
-    ] {
+    for program in [&tc.rrc_path, &tc.rene_path, &cfg.compiler.llvm_strip_path] {
         if let Some(parent) = program.parent() {
             if !parent.as_os_str().is_empty() {
                 paths.push(parent.to_owned());
@@ -461,13 +1336,42 @@ fn tool_ro_paths(cfg: &Config) -> Vec<PathBuf> {
     paths
 }
 
-fn ensure_success(name: &str, output: &std::process::Output) -> Result<()> {
+/// A tool exited unsuccessfully. Kept as a typed error so `handle` can parse
+/// its output into structured diagnostics.
+#[derive(Debug)]
+struct ToolFailure {
+    name: String,
+    /// Tool output with server paths hidden.
+    output: String,
+}
+
+impl std::fmt::Display for ToolFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} failed:\n{}", self.name, self.output)
+    }
+}
+
+impl std::error::Error for ToolFailure {}
+
+/// Fail with a [`ToolFailure`] unless `output` reports success. Paths under
+/// `package_root` and `build_dir` are hidden from the reported output.
+pub fn ensure_success(
+    name: &str,
+    output: &std::process::Output,
+    package_root: Option<&Path>,
+    build_dir: &Path,
+) -> Result<()> {
     if output.status.success() {
         return Ok(());
     }
     let stdout = String::from_utf8_lossy(&output.stdout);
     let stderr = String::from_utf8_lossy(&output.stderr);
-    anyhow::bail!("{name} failed:\n{stdout}{stderr}")
+    let output = format!("{stdout}{stderr}");
+    Err(ToolFailure {
+        name: name.to_owned(),
+        output: diagnostics::hide_server_paths(&output, package_root, build_dir),
+    }
+    .into())
 }
 
 fn ensure_inside(path: &Path, root: &Path) -> Result<()> {
@@ -496,6 +1400,184 @@ mod tests {
         assert_eq!(json["output"], "ok");
         assert!(json.get("wasm").is_none());
         assert!(json.get("error").is_none());
+        assert!(json.get("cached").is_none());
+    }
+
+    #[test]
+    fn wasm_is_base64_in_json() {
+        let json = serde_json::to_value(CompileResponse::wasm(vec![0, 97, 115, 109])).unwrap();
+        assert_eq!(json["wasm"], "AGFzbQ==");
+    }
+
+    #[test]
+    fn accept_header_selects_raw_wasm() {
+        let mut headers = HeaderMap::new();
+        assert!(!accepts_wasm(&headers));
+        headers.insert(
+            header::ACCEPT,
+            HeaderValue::from_static("application/wasm;q=1, application/json;q=0.5"),
+        );
+        assert!(accepts_wasm(&headers));
+        headers.insert(
+            header::ACCEPT,
+            HeaderValue::from_static("application/json, application/wasm; q=0"),
+        );
+        assert!(!accepts_wasm(&headers));
+    }
+
+    #[tokio::test]
+    async fn streams_phases_then_the_result() {
+        let response = stream_events(|progress| async move {
+            progress.phase(Phase::Queued);
+            progress.phase(Phase::BakingRuntime);
+            progress.phase(Phase::Compiling);
+            CompileResponse::text("ok".to_owned())
+        });
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        let events: Vec<&str> = body
+            .lines()
+            .filter(|line| line.starts_with("event:") || line.starts_with("data:"))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                "event: phase",
+                r#"data: {"phase":"queued"}"#,
+                "event: phase",
+                r#"data: {"phase":"baking_runtime"}"#,
+                "event: phase",
+                r#"data: {"phase":"compiling"}"#,
+                "event: phase",
+                r#"data: {"phase":"done"}"#,
+                "event: result",
+                r#"data: {"success":true,"output":"ok"}"#,
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn streamed_output_hides_server_paths() {
+        let (events_tx, mut events_rx) = mpsc::unbounded_channel();
+        let output: &[u8] = b"/build/target/wasm32-wasip1/release/main.wasm\n";
+        let bytes = collect_lines(
+            output,
+            "rene",
+            Stream::Stdout,
+            Some(Path::new("/build/requests/pkg")),
+            Path::new("/build"),
+            &Progress::new(events_tx),
+        )
+        .await
+        .unwrap();
+        assert_eq!(bytes, output);
+        let event = serde_json::to_value(events_rx.recv().await.unwrap()).unwrap();
+        assert_eq!(
+            event["line"],
+            "<build_dir>/target/wasm32-wasip1/release/main.wasm"
+        );
+    }
+
+    #[tokio::test]
+    async fn wat_disassembles_the_module() {
+        let wasm = b"\0asm\x01\0\0\0\x05\x03\x01\0\x01".to_vec();
+        assert_eq!(
+            disassemble(wasm).await.unwrap(),
+            "(module\n  (memory (;0;) 1)\n)\n"
+        );
+        assert!(disassemble(b"not wasm".to_vec()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn oversized_bodies_carry_a_reason() {
+        use tower::ServiceExt;
+
+        async fn echo(RequestJson(req): RequestJson<CompileRequest>) -> String {
+            req.source
+        }
+        let app = axum::Router::new()
+            .route("/", axum::routing::post(echo))
+            .layer(axum::extract::DefaultBodyLimit::max(16));
+        let req = Request::builder()
+            .method("POST")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(axum::body::Body::from(vec![b' '; 64]))
+            .unwrap();
+        let response = app.oneshot(req).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["success"], false);
+        assert_eq!(json["reason"], "body_too_large");
+    }
+
+    #[test]
+    fn tool_failures_carry_diagnostics() {
+        let output = std::process::Output {
+            status: std::os::unix::process::ExitStatusExt::from_raw(1 << 8),
+            stdout: Vec::new(),
+            stderr: b"/build/requests/pkg/src/lib.rr:1:2: error: bad\n".to_vec(),
+        };
+        let error = ensure_success(
+            "rrc",
+            &output,
+            Some(Path::new("/build/requests/pkg")),
+            Path::new("/build"),
+        )
+        .unwrap_err();
+        let json = serde_json::to_value(CompileResponse::failure(&error)).unwrap();
+        assert_eq!(json["error"], "rrc failed:\nsrc/lib.rr:1:2: error: bad\n");
+        assert_eq!(json["diagnostics"][0]["file"], "src/lib.rr");
+        assert!(json.get("raw").is_none());
+    }
+
+    #[test]
+    fn package_paths_reject_traversal() {
+        assert!(package_source_path("util/math.rr").is_ok());
+        assert!(package_source_path("../escape.rr").is_err());
+        assert!(package_source_path("/etc/passwd.rr").is_err());
+        assert!(package_source_path("./lib.rr").is_err());
+        assert!(package_source_path("notes.txt").is_err());
+    }
+
+    #[test]
+    fn package_limits_are_enforced() {
+        let limits = LimitsConfig {
+            max_body_bytes: 1024,
+            max_source_bytes: 8,
+            max_files: 2,
+        };
+        let request = |source: &str, files: &[(&str, &str)]| {
+            CompileRequest::new(
+                source.to_owned(),
+                files
+                    .iter()
+                    .map(|(path, contents)| ((*path).to_owned(), (*contents).to_owned()))
+                    .collect(),
+                "run".to_owned(),
+                serde_json::from_str("{}").unwrap(),
+            )
+        };
+        let reason = |req: CompileRequest| {
+            let error = req.package_files(&limits).unwrap_err();
+            error.downcast_ref::<Rejected>().unwrap().reason
+        };
+        assert!(request("main", &[("a.rr", "a")])
+            .package_files(&limits)
+            .is_ok());
+        assert_eq!(
+            reason(request("main", &[("a.rr", ""), ("b.rr", "")])),
+            FailureReason::TooManyFiles
+        );
+        assert_eq!(
+            reason(request("main", &[("a.rr", "12345")])),
+            FailureReason::SourceTooLarge
+        );
+        assert_eq!(reason(request("ma\0n", &[])), FailureReason::NulByte);
     }
 
     #[test]
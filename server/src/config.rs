@@ -1,6 +1,7 @@
 use anyhow::{Context, Result};
-use serde::Deserialize;
-use std::net::SocketAddr;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::{IpAddr, SocketAddr};
 use std::path::{Path, PathBuf};
 
 // ---------------------------------------------------------------------------
@@ -8,6 +9,7 @@ use std::path::{Path, PathBuf};
 // ---------------------------------------------------------------------------
 
 #[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
     /// Address to listen on.  Overridable via `--bind` CLI flag.
     #[serde(default = "defaults::bind_addr")]
@@ -17,6 +19,24 @@ pub struct Config {
 
     #[serde(default)]
     pub sandbox: SandboxConfig,
+
+    #[serde(default)]
+    pub share: ShareConfig,
+
+    #[serde(default)]
+    pub runtime: RuntimeConfig,
+
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+
+    #[serde(default)]
+    pub limits: LimitsConfig,
+
+    #[serde(default)]
+    pub auth: AuthConfig,
+
+    #[serde(default)]
+    pub warm_up: WarmUpConfig,
 }
 
 // ---------------------------------------------------------------------------
@@ -24,8 +44,11 @@ pub struct Config {
 // ---------------------------------------------------------------------------
 
 #[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct CompilerConfig {
-    /// Path to the `rrc` compiler binary from a Reussir nightly.
+    /// Path to the `rrc` compiler binary from a Reussir nightly. Together
+    /// with `rene_path`, `rustc_path`, `cargo_path`, `build_dir`, and
+    /// `cargo_home` this forms the toolchain named `default`.
     pub rrc_path: PathBuf,
 
     /// Path to the `rene` package manager from the same nightly.
@@ -44,6 +67,17 @@ pub struct CompilerConfig {
     #[serde(default = "defaults::build_dir")]
     pub build_dir: PathBuf,
 
+    /// Independent Rene build directories per toolchain. Builds in different
+    /// directories run in parallel; each directory bakes its own runtime on
+    /// first use.
+    #[serde(default = "defaults::build_slots")]
+    pub build_slots: usize,
+
+    /// Builds per toolchain that may wait for a free build directory. Further
+    /// requests are turned away with `503 Service Unavailable`.
+    #[serde(default = "defaults::queue_depth")]
+    pub queue_depth: usize,
+
     /// Writable Cargo home used while Rene bakes its embedded runtime.
     /// Defaults to `<build_dir>/cargo-home`.
     pub cargo_home: Option<PathBuf>,
@@ -60,9 +94,79 @@ pub struct CompilerConfig {
     /// Timeout for a Rene build, including a first-use runtime bake.
     #[serde(default = "defaults::build_timeout_secs")]
     pub build_timeout_secs: u64,
+
+    /// Deadline for a whole compile request, including time spent queued.
+    #[serde(default = "defaults::request_timeout_secs")]
+    pub request_timeout_secs: u64,
+
+    /// Directory of cached compile results. Defaults to `<build_dir>-cache`,
+    /// a sibling of the build directory; it must lie outside every
+    /// toolchain's `build_dir` so sandboxed tools cannot rewrite it.
+    pub cache_dir: Option<PathBuf>,
+
+    /// Size cap for cached compile results in `cache_dir`.
+    /// Least recently used entries are evicted first; `0` disables caching.
+    #[serde(default = "defaults::cache_max_bytes")]
+    pub cache_max_bytes: u64,
+
+    /// Additional named toolchains, e.g. a pinned older nightly for
+    /// regression triage. `Config::load` also inserts the `default` entry
+    /// built from the fields above, so after loading this holds every
+    /// toolchain a request may select.
+    #[serde(default)]
+    pub toolchains: BTreeMap<String, ToolchainConfig>,
+
+    /// Toolchain used when a request does not name one.
+    #[serde(default = "defaults::default_toolchain")]
+    pub default_toolchain: String,
+
+    /// Text modes served by running rrc with `--emit`, declared as
+    /// `[[compiler.emit_modes]]`. Replaces the built-in LLVM IR, assembly,
+    /// and MLIR modes when present.
+    #[serde(default = "defaults::emit_modes")]
+    pub emit_modes: Vec<EmitModeConfig>,
 }
 
-impl CompilerConfig {
+/// One text mode: the request `mode` name and how rrc produces it.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct EmitModeConfig {
+    pub name: String,
+    /// Value passed to `rrc --emit`.
+    pub emit: String,
+    /// Extension of rrc's output file and of client downloads.
+    pub extension: String,
+    /// Human-readable name for mode selectors.
+    pub label: String,
+    /// Extra rrc arguments appended after the standard ones.
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// One selectable Reussir toolchain with its own isolated Rene build
+/// directory.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ToolchainConfig {
+    pub rrc_path: PathBuf,
+    pub rene_path: PathBuf,
+
+    /// Rust toolchain overrides; fall back to `compiler.rustc_path` /
+    /// `compiler.cargo_path`, then PATH.
+    pub rustc_path: Option<PathBuf>,
+    pub cargo_path: Option<PathBuf>,
+
+    /// Defaults to `<compiler.build_dir>-<name>`, a sibling of the default
+    /// toolchain's build directory. Must not overlap another toolchain's,
+    /// since each is writable inside its toolchain's sandbox.
+    #[serde(default)]
+    pub build_dir: PathBuf,
+
+    /// Defaults to `<build_dir>/cargo-home`.
+    pub cargo_home: Option<PathBuf>,
+}
+
+impl ToolchainConfig {
     pub fn cargo_home(&self) -> PathBuf {
         self.cargo_home
             .clone()
@@ -70,12 +174,20 @@ impl CompilerConfig {
     }
 }
 
+impl CompilerConfig {
+    pub fn cache_dir(&self) -> PathBuf {
+        self.cache_dir
+            .clone()
+            .unwrap_or_else(|| sibling_build_dir(&self.build_dir, "cache"))
+    }
+}
+
 // ---------------------------------------------------------------------------
 // Sandbox config
 // ---------------------------------------------------------------------------
 
 #[derive(Deserialize, Debug, Clone)]
-#[serde(default)]
+#[serde(default, deny_unknown_fields)]
 pub struct SandboxConfig {
     pub kind: SandboxKind,
     /// Path to the `bwrap` binary.  Falls back to searching PATH.
@@ -91,7 +203,7 @@ impl Default for SandboxConfig {
     }
 }
 
-#[derive(Deserialize, Debug, Clone, Default)]
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default)]
 #[serde(rename_all = "lowercase")]
 pub enum SandboxKind {
     /// Wrap the compiler in bubblewrap (recommended; requires bwrap in PATH or
@@ -104,6 +216,217 @@ pub enum SandboxKind {
     None,
 }
 
+// ---------------------------------------------------------------------------
+// Share config
+// ---------------------------------------------------------------------------
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShareConfig {
+    /// Directory holding shared snippets, one file per ID.
+    pub dir: PathBuf,
+    /// Largest accepted snippet, measured as stored JSON.
+    pub max_bytes: usize,
+    /// Size cap for the whole store; new snippets are refused past it.
+    pub max_total_bytes: u64,
+    /// Delete snippets not shared again within this many seconds. Snippets
+    /// never expire when unset.
+    pub expiry_secs: Option<u64>,
+}
+
+impl Default for ShareConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("playground-shares"),
+            max_bytes: 64 * 1024,
+            max_total_bytes: 256 * 1024 * 1024,
+            expiry_secs: None,
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Request limits config
+// ---------------------------------------------------------------------------
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    /// Largest accepted request body on any API route.
+    pub max_body_bytes: usize,
+    /// Largest total size of a request's package sources.
+    pub max_source_bytes: usize,
+    /// Most source files in one package, `source` included.
+    pub max_files: usize,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_body_bytes: 1024 * 1024,
+            max_source_bytes: 256 * 1024,
+            max_files: 64,
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Warm-up config
+// ---------------------------------------------------------------------------
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct WarmUpConfig {
+    /// Bake every build directory's runtime at startup, with a target-less
+    /// and an executable Rene build per optimization level.
+    pub enabled: bool,
+    /// Serve requests while warming up; `/readyz` reports not ready until
+    /// the warm-up finishes. Otherwise the server only binds afterwards.
+    pub background: bool,
+    /// Sources compiled in run mode at every optimization level of the
+    /// default toolchain once the runtimes are baked, so their results are
+    /// cached.
+    pub examples: Vec<PathBuf>,
+}
+
+impl Default for WarmUpConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            background: true,
+            examples: Vec::new(),
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Auth config
+// ---------------------------------------------------------------------------
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// Bearer tokens accepted on the API routes. Authentication is off when
+    /// no keys are configured. `Config::load` appends the entries of
+    /// `keys_file`.
+    pub keys: Vec<ApiKeyConfig>,
+    /// TOML file with further `[[keys]]` entries, kept out of the main config.
+    pub keys_file: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ApiKeyConfig {
+    pub key: String,
+    /// Names the key in logs; unique across keys.
+    pub label: String,
+    /// Budgets for requests with this key, replacing `[rate_limit]`. Unset
+    /// budgets fall back to the global ones, counted per key.
+    #[serde(default)]
+    pub rate_limit: KeyRateLimit,
+    /// Replaces `compiler.request_timeout_secs`.
+    pub request_timeout_secs: Option<u64>,
+    /// Allows `POST /api/admin/reload`.
+    #[serde(default)]
+    pub admin: bool,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeyRateLimit {
+    pub run: Option<BucketConfig>,
+    pub text: Option<BucketConfig>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeysFile {
+    #[serde(default)]
+    keys: Vec<ApiKeyConfig>,
+}
+
+// ---------------------------------------------------------------------------
+// Rate limit config
+// ---------------------------------------------------------------------------
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    /// Budget for `run` and `wat` builds, which link a full executable.
+    /// Unlimited when unset.
+    pub run: Option<BucketConfig>,
+    /// Budget for text-mode builds. Unlimited when unset.
+    pub text: Option<BucketConfig>,
+    /// Reverse proxies whose `client_ip_header` is trusted to name the
+    /// client. Requests from any other peer are keyed by the peer address.
+    pub trusted_proxies: Vec<IpAddr>,
+    /// Header a trusted proxy appends the client address to.
+    pub client_ip_header: String,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            run: None,
+            text: None,
+            trusted_proxies: Vec::new(),
+            client_ip_header: "x-forwarded-for".to_owned(),
+        }
+    }
+}
+
+/// A token bucket: `burst` builds at once, refilled at `per_minute`.
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct BucketConfig {
+    pub burst: u32,
+    pub per_minute: u32,
+}
+
+// ---------------------------------------------------------------------------
+// Browser runtime config
+// ---------------------------------------------------------------------------
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct RuntimeConfig {
+    /// Function imports the frontend WASI shim implements, keyed by import
+    /// module. Run-mode modules importing anything else get a warning.
+    pub supported_imports: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl Default for RuntimeConfig {
+    fn default() -> Self {
+        let wasi = [
+            "args_get",
+            "args_sizes_get",
+            "environ_get",
+            "environ_sizes_get",
+            "clock_res_get",
+            "clock_time_get",
+            "fd_advise",
+            "fd_close",
+            "fd_datasync",
+            "fd_fdstat_get",
+            "fd_fdstat_set_flags",
+            "fd_prestat_dir_name",
+            "fd_prestat_get",
+            "fd_read",
+            "fd_sync",
+            "fd_write",
+            "proc_exit",
+            "random_get",
+            "sched_yield",
+        ];
+        Self {
+            supported_imports: BTreeMap::from([(
+                "wasi_snapshot_preview1".to_owned(),
+                wasi.into_iter().map(str::to_owned).collect(),
+            )]),
+        }
+    }
+}
+
 // ---------------------------------------------------------------------------
 // Default helpers
 // ---------------------------------------------------------------------------
@@ -120,6 +443,14 @@ mod defaults {
         PathBuf::from("playground-build")
     }
 
+    pub fn build_slots() -> usize {
+        1
+    }
+
+    pub fn queue_depth() -> usize {
+        16
+    }
+
     pub fn compile_timeout_secs() -> u64 {
         30
     }
@@ -127,14 +458,170 @@ mod defaults {
     pub fn build_timeout_secs() -> u64 {
         300
     }
+
+    pub fn request_timeout_secs() -> u64 {
+        600
+    }
+
+    pub fn cache_max_bytes() -> u64 {
+        256 * 1024 * 1024
+    }
+
+    pub fn default_toolchain() -> String {
+        super::DEFAULT_TOOLCHAIN.to_owned()
+    }
+
+    pub fn emit_modes() -> Vec<super::EmitModeConfig> {
+        [
+            ("llvm-ir", "llvm-ir", "ll", "LLVM IR"),
+            ("asm", "asm", "s", "WebAssembly"),
+            ("mlir", "mlir", "mlir", "MLIR"),
+        ]
+        .into_iter()
+        .map(|(name, emit, extension, label)| super::EmitModeConfig {
+            name: name.to_owned(),
+            emit: emit.to_owned(),
+            extension: extension.to_owned(),
+            label: label.to_owned(),
+            args: Vec::new(),
+        })
+        .collect()
+    }
 }
 
 // ---------------------------------------------------------------------------
 // Loading / validation
 // ---------------------------------------------------------------------------
 
+/// Name of the toolchain described directly by `[compiler]`.
+pub const DEFAULT_TOOLCHAIN: &str = "default";
+
+/// Prefix of the environment variables that override config keys.
+pub const ENV_PREFIX: &str = "REUSSIR_PLAYGROUND_";
+
+/// Where the config came from, so a reload can read it the same way.
+#[derive(Debug, Clone)]
+pub struct ConfigSource {
+    pub path: PathBuf,
+    /// `--set` flags, in order.
+    pub overrides: Vec<Override>,
+    /// `--bind`, which replaces `bind_addr`.
+    pub bind_addr: Option<SocketAddr>,
+}
+
+impl ConfigSource {
+    /// Load the file with overrides applied: `REUSSIR_PLAYGROUND_*`
+    /// variables first, then `--set` flags, then `--bind`.
+    pub fn load(&self) -> Result<Config> {
+        let mut overrides = Override::from_env()?;
+        overrides.extend(self.overrides.iter().cloned());
+        let mut cfg = Config::load(&self.path, &overrides)?;
+        if let Some(bind) = self.bind_addr {
+            cfg.bind_addr = bind;
+        }
+        Ok(cfg)
+    }
+}
+
+/// A `key=value` override of one config key, from `--set` or the
+/// environment.
+#[derive(Debug, Clone)]
+pub struct Override {
+    /// Dotted key path, e.g. `compiler.build_slots`.
+    key: String,
+    value: toml::Value,
+    /// Where the override came from, for errors and logs.
+    origin: String,
+}
+
+impl std::str::FromStr for Override {
+    type Err = String;
+
+    fn from_str(arg: &str) -> Result<Self, Self::Err> {
+        let (key, value) = arg
+            .split_once('=')
+            .ok_or_else(|| format!("expected KEY=VALUE, got {arg:?}"))?;
+        Ok(Self::new(key.trim(), value, "--set".to_owned()))
+    }
+}
+
+impl Override {
+    /// `raw` is read as a TOML value, or as a string when it is not one, so
+    /// `4`, `true`, and `["/opt"]` keep their types and paths need no quotes.
+    fn new(key: &str, raw: &str, origin: String) -> Self {
+        let value = toml::from_str::<toml::Table>(&format!("value = {raw}"))
+            .ok()
+            .and_then(|mut table| table.remove("value"))
+            .unwrap_or_else(|| toml::Value::String(raw.to_owned()));
+        Self {
+            key: key.to_owned(),
+            value,
+            origin,
+        }
+    }
+
+    /// Overrides from `REUSSIR_PLAYGROUND_*` variables, sorted by name. `__`
+    /// separates tables, so `REUSSIR_PLAYGROUND_COMPILER__BUILD_SLOTS` sets
+    /// `compiler.build_slots`.
+    pub fn from_env() -> Result<Vec<Self>> {
+        let mut vars: Vec<(String, String)> = std::env::vars_os()
+            .filter_map(|(name, value)| {
+                let name = name.into_string().ok()?;
+                name.starts_with(ENV_PREFIX).then_some((name, value))
+            })
+            .map(|(name, value)| {
+                let value = value
+                    .into_string()
+                    .map_err(|_| anyhow::anyhow!("{name} is not valid UTF-8"))?;
+                Ok((name, value))
+            })
+            .collect::<Result<_>>()?;
+        vars.sort();
+        Ok(vars
+            .into_iter()
+            .map(|(name, value)| {
+                let key = name[ENV_PREFIX.len()..]
+                    .to_lowercase()
+                    .split("__")
+                    .collect::<Vec<_>>()
+                    .join(".");
+                Self::new(&key, &value, name)
+            })
+            .collect())
+    }
+
+    /// Set the key in `table`, creating the tables on its path.
+    fn apply(&self, table: &mut toml::Table) -> Result<()> {
+        let mut segments: Vec<&str> = self.key.split('.').collect();
+        let last = segments.pop().unwrap_or_default();
+        anyhow::ensure!(
+            !last.is_empty() && segments.iter().all(|segment| !segment.is_empty()),
+            "{}: invalid config key {:?}",
+            self.origin,
+            self.key
+        );
+        let mut table = table;
+        for segment in segments {
+            let entry = table
+                .entry(segment)
+                .or_insert_with(|| toml::Value::Table(Default::default()));
+            table = entry.as_table_mut().with_context(|| {
+                format!(
+                    "{}: cannot set {}, {segment} is not a table",
+                    self.origin, self.key
+                )
+            })?;
+        }
+        table.insert(last.to_owned(), self.value.clone());
+        tracing::info!("config key {} set by {}", self.key, self.origin);
+        Ok(())
+    }
+}
+
 impl Config {
-    pub fn load(path: &Path) -> Result<Self> {
+    /// Load the file at `path`, applying `overrides` in order before
+    /// relative paths are resolved and the result validated.
+    pub fn load(path: &Path, overrides: &[Override]) -> Result<Self> {
         let config_path = std::fs::canonicalize(path)
             .with_context(|| format!("cannot resolve config file: {}", path.display()))?;
         let config_dir = config_path
@@ -142,8 +629,19 @@ impl Config {
             .context("config file has no parent directory")?;
         let text = std::fs::read_to_string(&config_path)
             .with_context(|| format!("cannot read config file: {}", path.display()))?;
-        let mut cfg: Config = toml::from_str(&text)
+        let mut table: toml::Table = toml::from_str(&text)
             .with_context(|| format!("failed to parse config file: {}", path.display()))?;
+        for config_override in overrides {
+            config_override.apply(&mut table)?;
+        }
+        let context = if overrides.is_empty() {
+            "failed to parse config file"
+        } else {
+            "invalid config with overrides"
+        };
+        let mut cfg: Config = toml::Value::Table(table)
+            .try_into()
+            .with_context(|| format!("{context}: {}", path.display()))?;
 
         resolve_relative(config_dir, &mut cfg.compiler.rrc_path);
         resolve_relative(config_dir, &mut cfg.compiler.rene_path);
@@ -154,47 +652,234 @@ impl Config {
         if let Some(cargo_home) = &mut cfg.compiler.cargo_home {
             resolve_relative(config_dir, cargo_home);
         }
+        resolve_optional_relative(config_dir, &mut cfg.compiler.cache_dir);
         for path in &mut cfg.compiler.toolchain_ro_paths {
             resolve_relative(config_dir, path);
         }
         resolve_optional_relative(config_dir, &mut cfg.sandbox.bwrap_path);
+        resolve_relative(config_dir, &mut cfg.share.dir);
+        for example in &mut cfg.warm_up.examples {
+            resolve_relative(config_dir, example);
+        }
+        if let Some(keys_file) = &mut cfg.auth.keys_file {
+            resolve_relative(config_dir, keys_file);
+            let text = std::fs::read_to_string(&*keys_file)
+                .with_context(|| format!("cannot read auth.keys_file: {}", keys_file.display()))?;
+            let file: KeysFile = toml::from_str(&text).with_context(|| {
+                format!("failed to parse auth.keys_file: {}", keys_file.display())
+            })?;
+            cfg.auth.keys.extend(file.keys);
+        }
+
+        let compiler = &mut cfg.compiler;
+        for (name, toolchain) in &mut compiler.toolchains {
+            resolve_relative(config_dir, &mut toolchain.rrc_path);
+            resolve_relative(config_dir, &mut toolchain.rene_path);
+            resolve_optional_relative(config_dir, &mut toolchain.rustc_path);
+            resolve_optional_relative(config_dir, &mut toolchain.cargo_path);
+            if toolchain.rustc_path.is_none() {
+                toolchain.rustc_path = compiler.rustc_path.clone();
+            }
+            if toolchain.cargo_path.is_none() {
+                toolchain.cargo_path = compiler.cargo_path.clone();
+            }
+            if toolchain.build_dir.as_os_str().is_empty() {
+                toolchain.build_dir = sibling_build_dir(&compiler.build_dir, name);
+            } else {
+                resolve_relative(config_dir, &mut toolchain.build_dir);
+            }
+            if let Some(cargo_home) = &mut toolchain.cargo_home {
+                resolve_relative(config_dir, cargo_home);
+            }
+        }
+        anyhow::ensure!(
+            !compiler.toolchains.contains_key(DEFAULT_TOOLCHAIN),
+            "compiler.toolchains.{DEFAULT_TOOLCHAIN} is reserved for the [compiler] toolchain"
+        );
+        compiler.toolchains.insert(
+            DEFAULT_TOOLCHAIN.to_owned(),
+            ToolchainConfig {
+                rrc_path: compiler.rrc_path.clone(),
+                rene_path: compiler.rene_path.clone(),
+                rustc_path: compiler.rustc_path.clone(),
+                cargo_path: compiler.cargo_path.clone(),
+                build_dir: compiler.build_dir.clone(),
+                cargo_home: compiler.cargo_home.clone(),
+            },
+        );
 
         cfg.validate()?;
         Ok(cfg)
     }
 
     fn validate(&self) -> Result<()> {
-        for (name, path) in [
-            ("compiler.rrc_path", &self.compiler.rrc_path),
-            ("compiler.rene_path", &self.compiler.rene_path),
-            ("compiler.llvm_strip_path", &self.compiler.llvm_strip_path),
+        anyhow::ensure!(
+            self.compiler.build_slots > 0,
+            "compiler.build_slots must be at least 1"
+        );
+        anyhow::ensure!(
+            self.compiler.llvm_strip_path.is_file(),
+            "compiler.llvm_strip_path does not exist: {}",
+            self.compiler.llvm_strip_path.display()
+        );
+        anyhow::ensure!(
+            self.compiler
+                .toolchains
+                .contains_key(&self.compiler.default_toolchain),
+            "compiler.default_toolchain names an unknown toolchain: {}",
+            self.compiler.default_toolchain
+        );
+        for (name, toolchain) in &self.compiler.toolchains {
+            let prefix = if name == DEFAULT_TOOLCHAIN {
+                "compiler".to_owned()
+            } else {
+                format!("compiler.toolchains.{name}")
+            };
+            validate_toolchain(&prefix, toolchain)?;
+            anyhow::ensure!(
+                !self.compiler.cache_dir().starts_with(&toolchain.build_dir),
+                "compiler.cache_dir must not lie inside the build_dir of toolchain {name}: {}",
+                self.compiler.cache_dir().display()
+            );
+            for (other, other_toolchain) in &self.compiler.toolchains {
+                anyhow::ensure!(
+                    other == name || !toolchain.build_dir.starts_with(&other_toolchain.build_dir),
+                    "{prefix}.build_dir must not lie inside the build_dir of toolchain {other}: {}",
+                    toolchain.build_dir.display()
+                );
+            }
+        }
+        validate_emit_modes(&self.compiler.emit_modes)?;
+        for (name, bucket) in [
+            ("run", &self.rate_limit.run),
+            ("text", &self.rate_limit.text),
         ] {
-            anyhow::ensure!(path.is_file(), "{name} does not exist: {}", path.display());
-        }
-        std::fs::create_dir_all(&self.compiler.build_dir).with_context(|| {
-            format!(
-                "cannot create compiler.build_dir: {}",
-                self.compiler.build_dir.display()
-            )
-        })?;
-        let cargo_home = self.compiler.cargo_home();
-        std::fs::create_dir_all(&cargo_home).with_context(|| {
-            format!(
-                "cannot create compiler.cargo_home: {}",
-                cargo_home.display()
-            )
-        })?;
-        let temp_dir = self.compiler.build_dir.join("tmp");
-        std::fs::create_dir_all(&temp_dir).with_context(|| {
-            format!(
-                "cannot create compiler temporary directory: {}",
-                temp_dir.display()
-            )
-        })?;
+            if let Some(bucket) = bucket {
+                anyhow::ensure!(
+                    bucket.burst > 0 && bucket.per_minute > 0,
+                    "rate_limit.{name}: burst and per_minute must be at least 1"
+                );
+            }
+        }
+        let mut keys = std::collections::HashSet::new();
+        let mut labels = std::collections::HashSet::new();
+        for key in &self.auth.keys {
+            let label = &key.label;
+            anyhow::ensure!(!label.trim().is_empty(), "auth.keys: empty label");
+            anyhow::ensure!(labels.insert(label), "auth.keys: duplicate label {label:?}");
+            anyhow::ensure!(
+                key.key.len() >= 16,
+                "auth.keys.{label}: key must be at least 16 characters"
+            );
+            anyhow::ensure!(
+                keys.insert(&key.key),
+                "auth.keys.{label}: key is already in use"
+            );
+            for (name, bucket) in [("run", &key.rate_limit.run), ("text", &key.rate_limit.text)] {
+                if let Some(bucket) = bucket {
+                    anyhow::ensure!(
+                        bucket.burst > 0 && bucket.per_minute > 0,
+                        "auth.keys.{label}.rate_limit.{name}: burst and per_minute must be at least 1"
+                    );
+                }
+            }
+        }
+        anyhow::ensure!(
+            axum::http::HeaderName::try_from(&self.rate_limit.client_ip_header).is_ok(),
+            "rate_limit.client_ip_header is not a valid header name: {:?}",
+            self.rate_limit.client_ip_header
+        );
+        for example in &self.warm_up.examples {
+            anyhow::ensure!(
+                example.is_file(),
+                "warm_up example not found: {}",
+                example.display()
+            );
+        }
         Ok(())
     }
 }
 
+/// `<build_dir>-<name>`, the default build directory of toolchain `name`.
+fn sibling_build_dir(build_dir: &Path, name: &str) -> PathBuf {
+    let base = build_dir.file_name().unwrap_or_default().to_string_lossy();
+    build_dir.with_file_name(format!("{base}-{name}"))
+}
+
+fn validate_emit_modes(modes: &[EmitModeConfig]) -> Result<()> {
+    let is_word = |value: &str| {
+        !value.is_empty()
+            && value
+                .bytes()
+                .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_')
+    };
+    let mut names = std::collections::BTreeSet::new();
+    for mode in modes {
+        let name = &mode.name;
+        anyhow::ensure!(
+            is_word(name),
+            "compiler.emit_modes: invalid mode name {name:?}"
+        );
+        anyhow::ensure!(
+            !crate::compile::RUN_MODES.contains(&name.as_str()),
+            "compiler.emit_modes: mode name {name:?} is reserved"
+        );
+        anyhow::ensure!(
+            names.insert(name),
+            "compiler.emit_modes: duplicate mode {name:?}"
+        );
+        anyhow::ensure!(
+            is_word(&mode.emit),
+            "compiler.emit_modes.{name}: invalid emit kind {:?}",
+            mode.emit
+        );
+        anyhow::ensure!(
+            is_word(&mode.extension),
+            "compiler.emit_modes.{name}: invalid extension {:?}",
+            mode.extension
+        );
+        anyhow::ensure!(
+            !mode.label.trim().is_empty(),
+            "compiler.emit_modes.{name}: label is empty"
+        );
+    }
+    Ok(())
+}
+
+fn validate_toolchain(prefix: &str, toolchain: &ToolchainConfig) -> Result<()> {
+    for (name, path) in [
+        ("rrc_path", &toolchain.rrc_path),
+        ("rene_path", &toolchain.rene_path),
+    ] {
+        anyhow::ensure!(
+            path.is_file(),
+            "{prefix}.{name} does not exist: {}",
+            path.display()
+        );
+    }
+    std::fs::create_dir_all(&toolchain.build_dir).with_context(|| {
+        format!(
+            "cannot create {prefix}.build_dir: {}",
+            toolchain.build_dir.display()
+        )
+    })?;
+    let cargo_home = toolchain.cargo_home();
+    std::fs::create_dir_all(&cargo_home).with_context(|| {
+        format!(
+            "cannot create {prefix}.cargo_home: {}",
+            cargo_home.display()
+        )
+    })?;
+    let temp_dir = toolchain.build_dir.join("tmp");
+    std::fs::create_dir_all(&temp_dir).with_context(|| {
+        format!(
+            "cannot create compiler temporary directory: {}",
+            temp_dir.display()
+        )
+    })?;
+    Ok(())
+}
+
 fn resolve_relative(base: &Path, path: &mut PathBuf) {
     if path.is_relative() {
         *path = base.join(&*path);
@@ -206,3 +891,82 @@ fn resolve_optional_relative(base: &Path, path: &mut Option<PathBuf>) {
         resolve_relative(base, path);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mode(name: &str, extension: &str) -> EmitModeConfig {
+        EmitModeConfig {
+            name: name.to_owned(),
+            emit: "llvm-ir".to_owned(),
+            extension: extension.to_owned(),
+            label: "LLVM IR".to_owned(),
+            args: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn emit_modes_are_validated() {
+        assert!(validate_emit_modes(&defaults::emit_modes()).is_ok());
+        assert!(validate_emit_modes(&[mode("wat", "wat")]).is_err());
+        assert!(validate_emit_modes(&[mode("ir", "ll"), mode("ir", "ll")]).is_err());
+        assert!(validate_emit_modes(&[mode("ir", "../ll")]).is_err());
+    }
+
+    #[test]
+    fn toolchain_build_dirs_are_siblings() {
+        assert_eq!(
+            sibling_build_dir(Path::new("/var/lib/rene"), "nightly"),
+            Path::new("/var/lib/rene-nightly")
+        );
+    }
+
+    #[test]
+    fn overrides_keep_toml_types() {
+        let mut table: toml::Table = toml::from_str("[compiler]\nbuild_slots = 1").unwrap();
+        let set = |arg: &str| arg.parse::<Override>().unwrap();
+        for arg in [
+            "compiler.build_slots=4",
+            "compiler.rustc_path=/usr/bin/rustc",
+            "compiler.toolchain_ro_paths=[\"/opt\"]",
+            "sandbox.kind=landlock",
+        ] {
+            set(arg).apply(&mut table).unwrap();
+        }
+        let expected: toml::Table = toml::from_str(
+            r#"
+            [compiler]
+            build_slots = 4
+            rustc_path = "/usr/bin/rustc"
+            toolchain_ro_paths = ["/opt"]
+            [sandbox]
+            kind = "landlock"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(table, expected);
+        assert!(set("compiler.build_slots.x=1").apply(&mut table).is_err());
+        assert!(set("compiler..x=1").apply(&mut table).is_err());
+        assert!("build_slots".parse::<Override>().is_err());
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        for example in [
+            include_str!("../../config.example.toml"),
+            include_str!("../../docker/config.landlock.toml"),
+        ] {
+            toml::from_str::<Config>(example).unwrap();
+        }
+        let mut table: toml::Table =
+            toml::from_str(include_str!("../../config.example.toml")).unwrap();
+        "compiler.bulid_slots=4"
+            .parse::<Override>()
+            .unwrap()
+            .apply(&mut table)
+            .unwrap();
+        let error = toml::Value::Table(table).try_into::<Config>().unwrap_err();
+        assert!(error.to_string().contains("bulid_slots"), "{error}");
+    }
+}
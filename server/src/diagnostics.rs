@@ -0,0 +1,311 @@
+//! Structured diagnostics parsed from rrc, Rene, and llvm-strip output.
+//!
+//! The tools report problems as human-readable text. Two layouts are
+//! recognised:
+//!
+//!  - **location prefix** — `path:line:col: error: message`, as printed by
+//!    MLIR and LLVM tools. A following `note:` line is attached to the
+//!    preceding diagnostic.
+//!  - **header + arrow** — `error[E0001]: message` or `[error]: message`
+//!    followed by a location line such as ` --> path:line:col`,
+//!    `╭─[path:line:col]`, or `╭──▶ path:line:col-line:col`.
+//!
+//! Lines that belong to neither layout (source excerpts, gutters, progress
+//! output) are kept verbatim in [`Parsed::raw`] so nothing is lost.
+
+use serde::Serialize;
+use std::path::Path;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+    Help,
+}
+
+impl Severity {
+    fn parse(text: &str) -> Option<Self> {
+        match text.trim().to_ascii_lowercase().as_str() {
+            "error" | "fatal error" | "fatal" => Some(Self::Error),
+            "warning" | "warn" => Some(Self::Warning),
+            "note" | "info" | "remark" => Some(Self::Note),
+            "help" | "hint" => Some(Self::Help),
+            _ => None,
+        }
+    }
+}
+
+/// A 1-based source range. `end_*` equal `start_*` when the tool only reports
+/// a single position.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start_line: u32,
+    pub start_column: u32,
+    pub end_line: u32,
+    pub end_column: u32,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    /// Path relative to the package root (e.g. `src/lib.rr`) when the
+    /// reported file lives inside the request package.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub span: Option<Span>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct Parsed {
+    pub diagnostics: Vec<Diagnostic>,
+    /// Output lines that could not be attributed to any diagnostic.
+    pub raw: String,
+}
+
+/// Rewrite server paths in tool output so clients never see the server
+/// directory layout: paths under `package_root` become relative to it, and
+/// the rest of `build_dir` is shown as `<build_dir>`.
+pub fn hide_server_paths(text: &str, package_root: Option<&Path>, build_dir: &Path) -> String {
+    let mut text = text.to_owned();
+    if let Some(root) = package_root {
+        let root = root.display().to_string();
+        text = text.replace(&format!("{root}/"), "").replace(&root, ".");
+    }
+    text.replace(&build_dir.display().to_string(), "<build_dir>")
+}
+
+/// Parse combined tool output, after [`hide_server_paths`].
+pub fn parse(output: &str) -> Parsed {
+    let mut parsed = Parsed::default();
+    // Index of the diagnostic that is still waiting for a location line.
+    let mut pending_location: Option<usize> = None;
+
+    for line in output.lines() {
+        if let Some((file, span, severity, message)) = parse_location_prefixed(line) {
+            match (severity, parsed.diagnostics.last_mut()) {
+                (Severity::Note | Severity::Help, Some(previous)) => {
+                    previous.notes.push(message.to_owned());
+                }
+                _ => parsed.diagnostics.push(Diagnostic {
+                    severity,
+                    message: message.to_owned(),
+                    file: Some(file.to_owned()),
+                    span: Some(span),
+                    notes: Vec::new(),
+                }),
+            }
+            pending_location = None;
+            continue;
+        }
+
+        if let Some((severity, message)) = parse_header(line) {
+            match (severity, parsed.diagnostics.last_mut()) {
+                (Severity::Note | Severity::Help, Some(previous)) if pending_location.is_none() => {
+                    previous.notes.push(message.to_owned());
+                }
+                _ => {
+                    parsed.diagnostics.push(Diagnostic {
+                        severity,
+                        message: message.to_owned(),
+                        file: None,
+                        span: None,
+                        notes: Vec::new(),
+                    });
+                    pending_location = Some(parsed.diagnostics.len() - 1);
+                }
+            }
+            continue;
+        }
+
+        if let Some(index) = pending_location {
+            if let Some((file, span)) = parse_arrow(line) {
+                let diagnostic = &mut parsed.diagnostics[index];
+                diagnostic.file = Some(file.to_owned());
+                diagnostic.span = Some(span);
+                pending_location = None;
+                continue;
+            }
+        }
+
+        if let Some(note) = parse_trailing_note(line) {
+            if let Some(previous) = parsed.diagnostics.last_mut() {
+                previous.notes.push(note.to_owned());
+                continue;
+            }
+        }
+
+        if !line.trim().is_empty() {
+            parsed.raw.push_str(line);
+            parsed.raw.push('\n');
+        }
+    }
+    parsed
+}
+
+/// `path:line:col[-line:col]: severity: message`
+fn parse_location_prefixed(line: &str) -> Option<(&str, Span, Severity, &str)> {
+    let (location, rest) = split_location(line.trim_start())?;
+    let rest = rest.strip_prefix(':')?.trim_start();
+    let (severity, message) = rest.split_once(':')?;
+    let severity = Severity::parse(severity)?;
+    let (file, span) = location;
+    Some((file, span, severity, message.trim()))
+}
+
+/// `error: message`, `error[E0001]: message`, or `[error]: message`
+fn parse_header(line: &str) -> Option<(Severity, &str)> {
+    let (head, message) = line.trim_start().split_once(':')?;
+    let head = head.trim();
+    let head = head
+        .strip_prefix('[')
+        .and_then(|h| h.strip_suffix(']'))
+        .unwrap_or(head);
+    let head = head.split_once('[').map_or(head, |(name, _code)| name);
+    let severity = Severity::parse(head)?;
+    let message = message.trim();
+    (!message.is_empty()).then_some((severity, message))
+}
+
+/// ` --> path:line:col`, `╭─[path:line:col]`, `╭──▶ path:line:col-line:col`
+fn parse_arrow(line: &str) -> Option<(&str, Span)> {
+    let trimmed = line.trim_start();
+    let rest = if let Some(rest) = trimmed.strip_prefix("-->") {
+        rest
+    } else if let Some(rest) = trimmed.strip_prefix('╭') {
+        rest.trim_start_matches('─')
+            .trim_start_matches('▶')
+            .trim_start_matches('[')
+    } else {
+        return None;
+    };
+    let ((file, span), tail) = split_location(rest.trim())?;
+    matches!(tail.trim(), "" | "]").then_some((file, span))
+}
+
+/// `= note: ...` / `= help: ...` lines inside a rustc-style excerpt.
+fn parse_trailing_note(line: &str) -> Option<&str> {
+    let rest = line.trim_start().strip_prefix('=')?.trim_start();
+    let (head, message) = rest.split_once(':')?;
+    matches!(Severity::parse(head)?, Severity::Note | Severity::Help).then_some(message.trim())
+}
+
+/// Split `path:line:col[-line:col]` from the front of `text`, returning the
+/// location and the remainder.
+fn split_location(text: &str) -> Option<((&str, Span), &str)> {
+    // Paths may contain ':' on exotic systems; search for the first
+    // `:<digits>:<digits>` suffix rather than splitting naïvely.
+    let mut search = 0;
+    while let Some(offset) = text[search..].find(':') {
+        let colon = search + offset;
+        if colon > 0 {
+            if let Some((start_line, start_column, after)) = parse_line_col(&text[colon + 1..]) {
+                let file = &text[..colon];
+                let mut span = Span {
+                    start_line,
+                    start_column,
+                    end_line: start_line,
+                    end_column: start_column,
+                };
+                let mut rest = after;
+                if let Some(range) = rest.strip_prefix('-') {
+                    if let Some((end_line, end_column, tail)) = parse_line_col(range) {
+                        span.end_line = end_line;
+                        span.end_column = end_column;
+                        rest = tail;
+                    }
+                }
+                if !file.contains(char::is_whitespace) {
+                    return Some(((file, span), rest));
+                }
+            }
+        }
+        search = colon + 1;
+    }
+    None
+}
+
+fn parse_line_col(text: &str) -> Option<(u32, u32, &str)> {
+    let (line, rest) = split_number(text)?;
+    let rest = rest.strip_prefix(':')?;
+    let (column, rest) = split_number(rest)?;
+    Some((line, column, rest))
+}
+
+fn split_number(text: &str) -> Option<(u32, &str)> {
+    let end = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let number = text[..end].parse().ok()?;
+    Some((number, &text[end..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn server_paths_are_hidden() {
+        let text = hide_server_paths(
+            "/build/requests/request-abc/src/lib.rr:3:7: error\n\
+             cannot read /build/slots/1/target/libreussir_rt.a\n\
+             in /build/requests/request-abc\n",
+            Some(Path::new("/build/requests/request-abc")),
+            Path::new("/build"),
+        );
+        assert_eq!(
+            text,
+            "src/lib.rr:3:7: error\n\
+             cannot read <build_dir>/slots/1/target/libreussir_rt.a\n\
+             in .\n"
+        );
+    }
+
+    #[test]
+    fn parses_location_prefixed_diagnostics_and_notes() {
+        let parsed = parse(
+            "src/lib.rr:3:7: error: unknown identifier `x`\n\
+             src/lib.rr:1:1: note: in function `main`\n",
+        );
+        assert_eq!(parsed.diagnostics.len(), 1);
+        let diagnostic = &parsed.diagnostics[0];
+        assert_eq!(diagnostic.severity, Severity::Error);
+        assert_eq!(diagnostic.file.as_deref(), Some("src/lib.rr"));
+        assert_eq!(diagnostic.span.unwrap().start_line, 3);
+        assert_eq!(diagnostic.span.unwrap().start_column, 7);
+        assert_eq!(diagnostic.notes, ["in function `main`"]);
+        assert!(parsed.raw.is_empty());
+    }
+
+    #[test]
+    fn parses_header_with_arrow_location_and_keeps_excerpt_raw() {
+        let parsed =
+            parse("[error]: type mismatch\n   ╭──▶ src/lib.rr:2:5-2:9\n   │\n 2 │ let y = 1.0;\n");
+        assert_eq!(parsed.diagnostics.len(), 1);
+        let diagnostic = &parsed.diagnostics[0];
+        assert_eq!(diagnostic.message, "type mismatch");
+        assert_eq!(diagnostic.file.as_deref(), Some("src/lib.rr"));
+        assert_eq!(
+            diagnostic.span,
+            Some(Span {
+                start_line: 2,
+                start_column: 5,
+                end_line: 2,
+                end_column: 9,
+            })
+        );
+        assert!(parsed.raw.contains("let y = 1.0;"));
+    }
+
+    #[test]
+    fn unparseable_output_is_preserved() {
+        let parsed = parse("thread 'main' panicked\n");
+        assert!(parsed.diagnostics.is_empty());
+        assert_eq!(parsed.raw, "thread 'main' panicked\n");
+    }
+}
@@ -0,0 +1,85 @@
+//! Liveness and readiness probes.
+//!
+//! `/healthz` answers as long as the process serves requests. `/readyz`
+//! answers `200` only once every configured toolchain has been probed and has
+//! at least one build directory with a baked `wasm32-wasip1` runtime, and
+//! the startup warm-up has finished, so a load balancer does not send the
+//! first users a multi-minute runtime build.
+//! Otherwise it answers `503`, and the body lists each check and why it failed.
+
+use crate::state::AppState;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::Serialize;
+
+#[derive(Serialize, Debug)]
+pub struct Readiness {
+    pub ready: bool,
+    pub checks: Vec<Check>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Check {
+    pub name: String,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl Check {
+    fn new(name: String, ok: bool, detail: Option<String>) -> Self {
+        Self { name, ok, detail }
+    }
+}
+
+pub async fn healthz() -> &'static str {
+    "ok"
+}
+
+pub async fn readyz(State(state): State<AppState>) -> Response {
+    let readiness = readiness(&state);
+    let status = if readiness.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(readiness)).into_response()
+}
+
+pub fn readiness(state: &AppState) -> Readiness {
+    let mut checks = Vec::new();
+    for name in state.config.compiler.toolchains.keys() {
+        let Some(toolchain) = state.toolchains.get(name) else {
+            checks.push(Check::new(
+                format!("toolchain {name} probed"),
+                false,
+                Some("toolchain has not been probed".to_owned()),
+            ));
+            continue;
+        };
+        checks.push(Check::new(format!("toolchain {name} probed"), true, None));
+        let baked = toolchain.builds.baked_slots();
+        let slots = toolchain.builds.slots();
+        checks.push(Check::new(
+            format!("toolchain {name} runtime baked"),
+            baked > 0,
+            Some(format!("{baked} of {slots} build directories baked")),
+        ));
+    }
+    let warm_up = &state.warm_up;
+    checks.push(Check::new(
+        "warm-up finished".to_owned(),
+        warm_up.finished(),
+        match (warm_up.finished(), warm_up.failures()) {
+            (false, _) => Some("warm-up in progress".to_owned()),
+            (true, 0) => None,
+            (true, failures) => Some(format!("{failures} warm-up builds failed")),
+        },
+    ));
+    Readiness {
+        ready: checks.iter().all(|check| check.ok),
+        checks,
+    }
+}
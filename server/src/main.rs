@@ -1,14 +1,41 @@
+mod auth;
+mod build_pool;
+mod cache;
+mod compare;
 mod compile;
 mod config;
+mod diagnostics;
+mod health;
+mod metrics;
+mod module_interface;
+mod progress;
+mod rate_limit;
+mod reload;
 mod sandbox;
+mod share;
+mod size_report;
+mod state;
+mod toolchain;
+mod warm_up;
 
-use axum::{routing::post, Router};
+use auth::KeyStore;
+use axum::{
+    extract::DefaultBodyLimit,
+    middleware,
+    routing::{get, post},
+    Router,
+};
+use cache::CompileCache;
 use clap::Parser;
-use config::Config;
+use config::{ConfigSource, Override};
+use rate_limit::RateLimiter;
+use share::ShareStore;
+use state::{AppState, SharedState};
 use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::sync::Arc;
 use tower_http::{cors::CorsLayer, services::ServeDir};
+use warm_up::WarmUp;
 
 // ---------------------------------------------------------------------------
 // CLI
@@ -21,6 +48,11 @@ struct Cli {
     #[arg(short, long, default_value = "config.toml")]
     config: PathBuf,
 
+    /// Override a config key, e.g. `--set compiler.build_slots=4`. Takes
+    /// precedence over `REUSSIR_PLAYGROUND_*` environment variables.
+    #[arg(long = "set", value_name = "KEY=VALUE")]
+    overrides: Vec<Override>,
+
     /// Override the bind address from the config file.
     #[arg(short, long)]
     bind: Option<SocketAddr>,
@@ -39,25 +71,76 @@ async fn main() -> anyhow::Result<()> {
         .init();
 
     let cli = Cli::parse();
-    let mut cfg = Config::load(&cli.config)?;
+    let source = ConfigSource {
+        path: cli.config,
+        overrides: cli.overrides,
+        bind_addr: cli.bind,
+    };
+    let cfg = source.load()?;
 
-    if let Some(bind) = cli.bind {
-        cfg.bind_addr = bind;
+    let bind_addr = cfg.bind_addr;
+    let max_body_bytes = cfg.limits.max_body_bytes;
+    let cache = CompileCache::open(cfg.compiler.cache_dir(), cfg.compiler.cache_max_bytes)?;
+    let shares = ShareStore::open(&cfg.share)?;
+    let toolchains = toolchain::probe_all(&cfg).await?;
+    let limits = RateLimiter::new(&cfg.rate_limit);
+    let keys = KeyStore::new(&cfg.auth);
+    if !cfg.auth.keys.is_empty() {
+        tracing::info!("API key authentication on, {} keys", cfg.auth.keys.len());
     }
+    let warm_up = WarmUp::new(&cfg.warm_up);
+    let state = AppState {
+        warm_up: Arc::new(warm_up),
+        config: Arc::new(cfg),
+        cache: Arc::new(cache),
+        toolchains: Arc::new(toolchains),
+        shares: Arc::new(shares),
+        limits: Arc::new(limits),
+        keys: Arc::new(keys),
+    };
 
-    let bind_addr = cfg.bind_addr;
-    let cfg = Arc::new(cfg);
+    if !state.warm_up.finished() {
+        if state.config.warm_up.background {
+            tokio::spawn(warm_up::run(state.clone()));
+        } else {
+            warm_up::run(state.clone()).await;
+        }
+    }
+    let shared = SharedState::new(state, source);
+    tokio::spawn(metrics::watch_disk_usage(shared.clone()));
+    tokio::spawn(share::sweep_expired(shared.clone()));
+    #[cfg(unix)]
+    tokio::spawn(reload::watch_sighup(shared.clone()));
 
     let frontend = ServeDir::new("frontend/dist").append_index_html_on_directories(true);
 
     let app = Router::new()
         .route("/api/compile", post(compile::handle))
-        .with_state(cfg)
+        .route("/api/compile/stream", post(compile::handle_stream))
+        .route("/api/compare", post(compare::handle))
+        .route("/api/toolchain", get(toolchain::handle))
+        .route("/api/share", post(share::create))
+        .route("/api/share/{id}", get(share::get))
+        .route("/api/admin/reload", post(reload::handle))
+        .route_layer(middleware::from_fn_with_state(
+            shared.clone(),
+            auth::require_key,
+        ))
+        .route("/healthz", get(health::healthz))
+        .route("/readyz", get(health::readyz))
+        .route("/metrics", get(metrics::handle))
+        .layer(DefaultBodyLimit::max(max_body_bytes))
+        .with_state(shared)
         .fallback_service(frontend)
         .layer(CorsLayer::permissive());
 
     tracing::info!("listening on http://{bind_addr}");
     let listener = tokio::net::TcpListener::bind(bind_addr).await?;
-    axum::serve(listener, app).await?;
+    // Rate limits key requests by peer address.
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await?;
     Ok(())
 }
@@ -0,0 +1,172 @@
+//! Prometheus metrics and the `/metrics` handler.
+//!
+//! Metrics live in one process-wide registry so deep call sites such as
+//! `run_sandboxed` can record without threading state through. Build
+//! directory sizes are measured in the background, since walking a Rene
+//! build directory on every scrape would be slow.
+
+use crate::state::SharedState;
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use prometheus::{
+    exponential_buckets, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+use std::path::Path;
+use std::sync::LazyLock;
+use std::time::Duration;
+
+/// How often build directory sizes are re-measured.
+const DISK_USAGE_INTERVAL: Duration = Duration::from_secs(60);
+
+pub struct Metrics {
+    registry: Registry,
+    /// Compiles by mode, optimization level, and outcome.
+    pub requests: IntCounterVec,
+    pub request_duration: HistogramVec,
+    /// Time builds waited for a free Rene build directory.
+    pub queue_wait: HistogramVec,
+    /// Wall time of each sandboxed tool run.
+    pub tool_duration: HistogramVec,
+    /// Tool runs and whole requests that hit their deadline.
+    pub timeouts: IntCounterVec,
+    /// Size of successful outputs: the module in run mode, the text otherwise.
+    pub output_bytes: HistogramVec,
+    /// Requests refused by rate limits, by budget.
+    pub rate_limited: IntCounterVec,
+    /// Refreshed every [`DISK_USAGE_INTERVAL`].
+    pub build_dir_bytes: IntGaugeVec,
+}
+
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(|| {
+    let registry =
+        Registry::new_custom(Some("playground".to_owned()), None).expect("metric prefix is valid");
+    let seconds = || exponential_buckets(0.01, 2.0, 16).expect("buckets are valid");
+    let counter = |name: &str, help: &str, labels: &[&str]| {
+        let metric = IntCounterVec::new(Opts::new(name, help), labels).expect("metric is valid");
+        registry
+            .register(Box::new(metric.clone()))
+            .expect("metric is registered once");
+        metric
+    };
+    let histogram = |name: &str, help: &str, labels: &[&str], buckets: Vec<f64>| {
+        let opts = HistogramOpts::new(name, help).buckets(buckets);
+        let metric = HistogramVec::new(opts, labels).expect("metric is valid");
+        registry
+            .register(Box::new(metric.clone()))
+            .expect("metric is registered once");
+        metric
+    };
+    let build_dir_bytes = IntGaugeVec::new(
+        Opts::new(
+            "build_dir_bytes",
+            "Disk usage of each toolchain's build directory",
+        ),
+        &["toolchain"],
+    )
+    .expect("metric is valid");
+    registry
+        .register(Box::new(build_dir_bytes.clone()))
+        .expect("metric is registered once");
+    Metrics {
+        requests: counter(
+            "requests_total",
+            "Compile requests by mode, optimization level, and outcome",
+            &["mode", "opt", "outcome"],
+        ),
+        request_duration: histogram(
+            "request_duration_seconds",
+            "Compile request duration",
+            &["mode", "outcome"],
+            seconds(),
+        ),
+        queue_wait: histogram(
+            "queue_wait_seconds",
+            "Time waiting for a free Rene build directory",
+            &["toolchain"],
+            seconds(),
+        ),
+        tool_duration: histogram(
+            "tool_duration_seconds",
+            "Sandboxed tool run duration",
+            &["tool"],
+            seconds(),
+        ),
+        timeouts: counter(
+            "timeouts_total",
+            "Tool runs and requests that exceeded their deadline",
+            &["kind"],
+        ),
+        output_bytes: histogram(
+            "output_bytes",
+            "Size of successful compile outputs",
+            &["mode"],
+            exponential_buckets(1024.0, 4.0, 10).expect("buckets are valid"),
+        ),
+        rate_limited: counter(
+            "rate_limited_total",
+            "Requests refused by rate limits",
+            &["budget"],
+        ),
+        build_dir_bytes,
+        registry,
+    }
+});
+
+pub async fn handle() -> Response {
+    match TextEncoder::new().encode_to_string(&METRICS.registry.gather()) {
+        Ok(text) => ([(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)], text).into_response(),
+        Err(error) => {
+            tracing::error!("cannot encode metrics: {error}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Keep `build_dir_bytes` current for every toolchain.
+pub async fn watch_disk_usage(shared: SharedState) {
+    let mut interval = tokio::time::interval(DISK_USAGE_INTERVAL);
+    loop {
+        interval.tick().await;
+        let state = shared.current();
+        for (name, toolchain) in state.toolchains.iter() {
+            let dir = toolchain.config.build_dir.clone();
+            match tokio::task::spawn_blocking(move || disk_usage(&dir)).await {
+                Ok(bytes) => METRICS
+                    .build_dir_bytes
+                    .with_label_values(&[name])
+                    .set(i64::try_from(bytes).unwrap_or(i64::MAX)),
+                Err(error) => tracing::warn!("cannot measure build_dir of {name}: {error}"),
+            }
+        }
+    }
+}
+
+/// Total size of the files under `dir`, skipping anything unreadable.
+fn disk_usage(dir: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .filter_map(Result::ok)
+        .map(|entry| match entry.file_type() {
+            Ok(kind) if kind.is_dir() => disk_usage(&entry.path()),
+            Ok(kind) if kind.is_file() => entry.metadata().map_or(0, |meta| meta.len()),
+            _ => 0,
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disk_usage_sums_nested_files() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a"), [0; 3]).unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        std::fs::write(dir.path().join("sub/b"), [0; 4]).unwrap();
+        assert_eq!(disk_usage(dir.path()), 7);
+    }
+}
@@ -0,0 +1,185 @@
+//! Imports and exports of run-mode modules, checked against the browser
+//! runtime.
+//!
+//! The frontend runs modules through a partial WASI shim. Importing a
+//! function the shim does not implement, or lacking the `_start` entry point
+//! or the `memory` the shim writes output through, only shows up as an error
+//! in the browser, so compiles report these as warnings up front. The
+//! supported set is `runtime.supported_imports`.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use wasmparser::{ExternalKind, Parser, Payload, TypeRef};
+
+/// Export the browser runtime calls to run the program.
+pub const ENTRY_EXPORT: &str = "_start";
+
+/// Export the browser runtime's WASI shim reads and writes through.
+pub const MEMORY_EXPORT: &str = "memory";
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ModuleInterface {
+    pub imports: Vec<Import>,
+    pub exports: Vec<Export>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Import {
+    pub module: String,
+    pub name: String,
+    pub kind: Kind,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Export {
+    pub name: String,
+    pub kind: Kind,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Func,
+    Table,
+    Memory,
+    Global,
+    Tag,
+}
+
+pub fn inspect(wasm: &[u8]) -> Result<ModuleInterface> {
+    let mut interface = ModuleInterface {
+        imports: Vec::new(),
+        exports: Vec::new(),
+    };
+    for payload in Parser::new(0).parse_all(wasm) {
+        match payload.context("malformed WASM module")? {
+            Payload::ImportSection(reader) => {
+                for import in reader.into_imports() {
+                    let import = import?;
+                    interface.imports.push(Import {
+                        module: import.module.to_owned(),
+                        name: import.name.to_owned(),
+                        kind: match import.ty {
+                            TypeRef::Func(_) | TypeRef::FuncExact(_) => Kind::Func,
+                            TypeRef::Table(_) => Kind::Table,
+                            TypeRef::Memory(_) => Kind::Memory,
+                            TypeRef::Global(_) => Kind::Global,
+                            TypeRef::Tag(_) => Kind::Tag,
+                        },
+                    });
+                }
+            }
+            Payload::ExportSection(reader) => {
+                for export in reader {
+                    let export = export?;
+                    interface.exports.push(Export {
+                        name: export.name.to_owned(),
+                        kind: match export.kind {
+                            ExternalKind::Func | ExternalKind::FuncExact => Kind::Func,
+                            ExternalKind::Table => Kind::Table,
+                            ExternalKind::Memory => Kind::Memory,
+                            ExternalKind::Global => Kind::Global,
+                            ExternalKind::Tag => Kind::Tag,
+                        },
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(interface)
+}
+
+impl ModuleInterface {
+    /// Problems the browser runtime would hit running this module.
+    pub fn warnings(&self, supported: &BTreeMap<String, BTreeSet<String>>) -> Vec<String> {
+        let mut warnings: Vec<String> = self
+            .imports
+            .iter()
+            .filter(|import| import.kind == Kind::Func)
+            .filter(|import| {
+                !supported
+                    .get(&import.module)
+                    .is_some_and(|names| names.contains(&import.name))
+            })
+            .map(|import| {
+                format!(
+                    "imports {}::{}, which the browser runtime does not support",
+                    import.module, import.name
+                )
+            })
+            .collect();
+        if !self
+            .exports
+            .iter()
+            .any(|export| export.name == ENTRY_EXPORT && export.kind == Kind::Func)
+        {
+            warnings.push(format!(
+                "does not export a `{ENTRY_EXPORT}` function; the browser cannot run it"
+            ));
+        }
+        if !self
+            .exports
+            .iter()
+            .any(|export| export.name == MEMORY_EXPORT && export.kind == Kind::Memory)
+        {
+            warnings.push(format!(
+                "does not export its `{MEMORY_EXPORT}`; the browser cannot run it"
+            ));
+        }
+        warnings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `(module (import "wasi_snapshot_preview1" "fd_write" (func))
+    /// (import "env" "f" (func)) (export "memory" (memory 0)) (memory 1))`.
+    const MODULE: &[u8] = &[
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+        0x01, 0x04, 0x01, 0x60, 0x00, 0x00, // type
+        0x02, 0x2b, 0x02, // import
+        0x16, b'w', b'a', b's', b'i', b'_', b's', b'n', b'a', b'p', b's', b'h', b'o', b't', b'_',
+        b'p', b'r', b'e', b'v', b'i', b'e', b'w', b'1', //
+        0x08, b'f', b'd', b'_', b'w', b'r', b'i', b't', b'e', 0x00, 0x00, //
+        0x03, b'e', b'n', b'v', 0x01, b'f', 0x00, 0x00, //
+        0x05, 0x03, 0x01, 0x00, 0x01, // memory
+        0x07, 0x0a, 0x01, 0x06, b'm', b'e', b'm', b'o', b'r', b'y', 0x02, 0x00, // export
+    ];
+
+    #[test]
+    fn warns_about_unsupported_imports_and_missing_entry() {
+        let interface = inspect(MODULE).unwrap();
+        assert_eq!(interface.imports.len(), 2);
+        assert_eq!(interface.exports[0].kind, Kind::Memory);
+        let supported = BTreeMap::from([(
+            "wasi_snapshot_preview1".to_owned(),
+            BTreeSet::from(["fd_write".to_owned()]),
+        )]);
+        assert_eq!(
+            interface.warnings(&supported),
+            [
+                "imports env::f, which the browser runtime does not support",
+                "does not export a `_start` function; the browser cannot run it",
+            ]
+        );
+    }
+
+    #[test]
+    fn warns_about_missing_memory() {
+        let interface = ModuleInterface {
+            imports: Vec::new(),
+            exports: vec![Export {
+                name: ENTRY_EXPORT.to_owned(),
+                kind: Kind::Func,
+            }],
+        };
+        assert_eq!(
+            interface.warnings(&BTreeMap::new()),
+            ["does not export its `memory`; the browser cannot run it"]
+        );
+    }
+}
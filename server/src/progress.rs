@@ -0,0 +1,108 @@
+//! Compile progress reporting for the streaming `/api/compile/stream` route.
+//!
+//! A [`Progress`] handle is threaded through the compile pipeline. The
+//! blocking `/api/compile` route uses [`Progress::disabled`], so every report
+//! is a no-op; the streaming route forwards reports to the client as
+//! server-sent events.
+
+use axum::response::sse::Event;
+use serde::Serialize;
+use tokio::sync::mpsc;
+
+/// Pipeline stage. Run and wat builds go `Queued`, `BakingRuntime`,
+/// `Stripping`, `Done`; text modes go `Queued`, `BakingRuntime`, `Compiling`,
+/// `Done`.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    /// Waiting for a free Rene build directory.
+    Queued,
+    /// Rene is preparing the runtime (baking it through Cargo on first use)
+    /// and, in run mode, building the executable.
+    BakingRuntime,
+    /// rrc is emitting the requested text output.
+    Compiling,
+    /// llvm-strip is removing non-runtime sections from the module.
+    Stripping,
+    Done,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(untagged)]
+pub enum ProgressEvent {
+    Phase {
+        phase: Phase,
+    },
+    /// Place in line for a build directory, 1 being next; sent while queued
+    /// whenever it changes.
+    Queue {
+        position: usize,
+    },
+    Output {
+        tool: String,
+        stream: Stream,
+        line: String,
+    },
+}
+
+impl ProgressEvent {
+    pub fn to_sse(&self) -> Event {
+        let name = match self {
+            Self::Phase { .. } => "phase",
+            Self::Queue { .. } => "queue",
+            Self::Output { .. } => "output",
+        };
+        Event::default()
+            .event(name)
+            .json_data(self)
+            .expect("progress events serialize to JSON")
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Progress {
+    sender: Option<mpsc::UnboundedSender<ProgressEvent>>,
+}
+
+impl Progress {
+    pub fn new(sender: mpsc::UnboundedSender<ProgressEvent>) -> Self {
+        Self {
+            sender: Some(sender),
+        }
+    }
+
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    pub fn phase(&self, phase: Phase) {
+        self.send(ProgressEvent::Phase { phase });
+    }
+
+    pub fn queue(&self, position: usize) {
+        self.send(ProgressEvent::Queue { position });
+    }
+
+    pub fn output(&self, tool: &str, stream: Stream, line: &str) {
+        self.send(ProgressEvent::Output {
+            tool: tool.to_owned(),
+            stream,
+            line: line.to_owned(),
+        });
+    }
+
+    fn send(&self, event: ProgressEvent) {
+        if let Some(sender) = &self.sender {
+            // The client may have disconnected; the compile is cancelled by
+            // the route handler in that case, so dropped events are fine.
+            let _ = sender.send(event);
+        }
+    }
+}
@@ -0,0 +1,310 @@
+//! Per-client rate limits for the compile routes.
+//!
+//! Each client IP gets one token bucket per budget: `run` for builds that link
+//! an executable and `text` for text modes, sized by `[rate_limit]`. Requests
+//! that find their bucket empty are rejected with `429 Too Many Requests`
+//! before any build work starts. Behind a reverse proxy, the client address is
+//! read from `client_ip_header`, but only when the connecting peer is one of
+//! `trusted_proxies`; otherwise any client could pick its own key.
+//!
+//! Requests made with an API key are counted per key instead, against the
+//! key's own budgets where it sets them.
+
+use crate::compile::RUN_MODES;
+use crate::config::{ApiKeyConfig, BucketConfig, RateLimitConfig};
+use crate::metrics::METRICS;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// Bucket count past which full, and therefore idle, buckets are dropped.
+const SWEEP_THRESHOLD: usize = 4096;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Budget {
+    Run,
+    Text,
+}
+
+impl Budget {
+    pub fn for_mode(mode: &str) -> Self {
+        if RUN_MODES.contains(&mode) {
+            Self::Run
+        } else {
+            Self::Text
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Run => "run",
+            Self::Text => "text",
+        }
+    }
+}
+
+/// A request over its budget. Serializes as the 429 response body, which
+/// keeps the `/api/compile` failure shape.
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct RateLimited {
+    success: bool,
+    error: String,
+    reason: &'static str,
+    budget: Budget,
+    /// Seconds until the request would be allowed.
+    retry_after: u64,
+}
+
+impl IntoResponse for RateLimited {
+    fn into_response(self) -> Response {
+        (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, self.retry_after.to_string())],
+            Json(self),
+        )
+            .into_response()
+    }
+}
+
+/// Whose budget a request spends.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Client {
+    Ip(IpAddr),
+    /// An API key, by label.
+    Key(String),
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    /// Tokens available at `now`.
+    fn refill(&self, limit: BucketConfig, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        let refilled = self.tokens + elapsed * f64::from(limit.per_minute) / 60.0;
+        refilled.min(f64::from(limit.burst))
+    }
+}
+
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    /// Shared with limiters made by [`RateLimiter::reconfigure`].
+    buckets: Arc<Mutex<HashMap<(Client, Budget), Bucket>>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig) -> Self {
+        Self {
+            config: config.clone(),
+            buckets: Arc::default(),
+        }
+    }
+
+    /// A limiter for a reloaded config that shares this one's buckets, so a
+    /// reload does not refill every client, and requests still holding this
+    /// limiter draw from the same buckets. Buckets are capped at the new
+    /// bursts as they refill.
+    pub fn reconfigure(&self, config: &RateLimitConfig) -> Self {
+        Self {
+            config: config.clone(),
+            buckets: self.buckets.clone(),
+        }
+    }
+
+    /// Take `cost` tokens from the `budget` of the request's key, or of its
+    /// client IP without one.
+    pub fn check(
+        &self,
+        peer: SocketAddr,
+        headers: &HeaderMap,
+        key: Option<&ApiKeyConfig>,
+        budget: Budget,
+        cost: u32,
+    ) -> Result<(), RateLimited> {
+        let (client, limit) = match key {
+            Some(key) => {
+                let limit = match budget {
+                    Budget::Run => key.rate_limit.run,
+                    Budget::Text => key.rate_limit.text,
+                };
+                (Client::Key(key.label.clone()), limit.or(self.limit(budget)))
+            }
+            None => (
+                Client::Ip(self.client_ip(peer.ip(), headers)),
+                self.limit(budget),
+            ),
+        };
+        self.check_at(client, limit, budget, cost, Instant::now())
+            .inspect_err(|_| {
+                METRICS
+                    .rate_limited
+                    .with_label_values(&[budget.name()])
+                    .inc();
+            })
+    }
+
+    fn check_at(
+        &self,
+        client: Client,
+        limit: Option<BucketConfig>,
+        budget: Budget,
+        cost: u32,
+        now: Instant,
+    ) -> Result<(), RateLimited> {
+        let Some(limit) = limit else {
+            return Ok(());
+        };
+        let burst = f64::from(limit.burst);
+        let per_sec = f64::from(limit.per_minute) / 60.0;
+        // A request costing more than the burst could never pass.
+        let cost = f64::from(cost).min(burst);
+
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= SWEEP_THRESHOLD {
+            // Buckets refilled to the global burst are idle. Per-key buckets
+            // are few and kept.
+            buckets.retain(|(client, budget), bucket| match client {
+                Client::Key(_) => true,
+                Client::Ip(_) => self
+                    .limit(*budget)
+                    .is_some_and(|limit| bucket.refill(limit, now) < f64::from(limit.burst)),
+            });
+        }
+        let bucket = buckets.entry((client, budget)).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+        });
+        bucket.tokens = bucket.refill(limit, now);
+        bucket.updated = now;
+        if bucket.tokens >= cost {
+            bucket.tokens -= cost;
+            return Ok(());
+        }
+        let retry_after = ((cost - bucket.tokens) / per_sec).ceil() as u64;
+        Err(RateLimited {
+            success: false,
+            error: format!(
+                "rate limit exceeded for {} builds; try again in {retry_after} seconds",
+                budget.name()
+            ),
+            reason: "rate_limited",
+            budget,
+            retry_after,
+        })
+    }
+
+    fn limit(&self, budget: Budget) -> Option<BucketConfig> {
+        match budget {
+            Budget::Run => self.config.run,
+            Budget::Text => self.config.text,
+        }
+    }
+
+    /// The address to key `peer`'s request by. A trusted proxy's header is
+    /// read right to left up to the first untrusted hop, so entries the client
+    /// forged at the front are never used.
+    fn client_ip(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        let trusted = &self.config.trusted_proxies;
+        if !trusted.contains(&peer) {
+            return peer;
+        }
+        let hops: Vec<&str> = headers
+            .get_all(self.config.client_ip_header.as_str())
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .collect();
+        let mut client = peer;
+        for hop in hops.into_iter().rev() {
+            let Ok(hop) = hop.trim().parse() else {
+                break;
+            };
+            client = hop;
+            if !trusted.contains(&hop) {
+                break;
+            }
+        }
+        client
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn limiter(trusted_proxies: &[&str]) -> RateLimiter {
+        RateLimiter::new(&RateLimitConfig {
+            run: Some(BucketConfig {
+                burst: 2,
+                per_minute: 6,
+            }),
+            text: None,
+            trusted_proxies: trusted_proxies
+                .iter()
+                .map(|ip| ip.parse().unwrap())
+                .collect(),
+            client_ip_header: "x-forwarded-for".to_owned(),
+        })
+    }
+
+    #[test]
+    fn buckets_refill_over_time() {
+        let limiter = limiter(&[]);
+        let check = |client: &str, budget, cost, at| {
+            let client = Client::Ip(client.parse().unwrap());
+            limiter.check_at(client, limiter.limit(budget), budget, cost, at)
+        };
+        let start = Instant::now();
+        assert!(check("192.0.2.1", Budget::Run, 1, start).is_ok());
+        assert!(check("192.0.2.1", Budget::Run, 1, start).is_ok());
+        let limited = check("192.0.2.1", Budget::Run, 1, start).unwrap_err();
+        assert_eq!(limited.retry_after, 10);
+        let later = start + Duration::from_secs(10);
+        assert!(check("192.0.2.1", Budget::Run, 1, later).is_ok());
+        assert!(check("192.0.2.1", Budget::Text, 100, start).is_ok());
+        assert!(check("192.0.2.2", Budget::Run, 1, start).is_ok());
+    }
+
+    #[test]
+    fn reconfigured_limiters_share_buckets() {
+        let old = limiter(&[]);
+        let new = old.reconfigure(&old.config);
+        let client = Client::Ip("192.0.2.1".parse().unwrap());
+        let start = Instant::now();
+        let limit = old.limit(Budget::Run);
+        assert!(old
+            .check_at(client.clone(), limit, Budget::Run, 2, start)
+            .is_ok());
+        assert!(new.check_at(client, limit, Budget::Run, 1, start).is_err());
+    }
+
+    #[test]
+    fn header_is_only_trusted_from_proxies() {
+        let limiter = limiter(&["10.0.0.1", "10.0.0.2"]);
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "x-forwarded-for",
+            "forged, 203.0.113.9, 198.51.100.7, 10.0.0.2"
+                .parse()
+                .unwrap(),
+        );
+        let proxy = "10.0.0.1".parse().unwrap();
+        assert_eq!(
+            limiter.client_ip(proxy, &headers),
+            "198.51.100.7".parse::<IpAddr>().unwrap()
+        );
+        let direct = "192.0.2.1".parse().unwrap();
+        assert_eq!(limiter.client_ip(direct, &headers), direct);
+        assert_eq!(limiter.client_ip(proxy, &HeaderMap::new()), proxy);
+    }
+}
@@ -0,0 +1,161 @@
+//! Config hot reload.
+//!
+//! On `SIGHUP` or `POST /api/admin/reload`, the config file is loaded and
+//! validated again and every toolchain re-probed. Only when all of that
+//! succeeds does the new state replace the old one; compiles already running
+//! finish with the config they started with. On failure the old config stays
+//! in effect and the reason is logged.
+//!
+//! The listen address, request body limit, and compile cache are fixed at
+//! startup; changes to them are logged and apply after a restart.
+
+use crate::auth::{ApiKey, KeyStore};
+use crate::config::Config;
+use crate::share::ShareStore;
+use crate::state::{AppState, SharedState};
+use crate::toolchain;
+use crate::warm_up;
+use anyhow::Result;
+use axum::extract::{ConnectInfo, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+/// Reload the config, logging the outcome; `trigger` names what asked.
+pub async fn reload(shared: &SharedState, trigger: &str) -> Result<()> {
+    let _reloading = shared.reloading.lock().await;
+    tracing::info!(
+        "reloading config from {} ({trigger})",
+        shared.source.path.display()
+    );
+    match try_reload(shared).await {
+        Ok(()) => {
+            tracing::info!("config reloaded");
+            Ok(())
+        }
+        Err(error) => {
+            tracing::error!("config reload failed, keeping the old config: {error:#}");
+            Err(error)
+        }
+    }
+}
+
+async fn try_reload(shared: &SharedState) -> Result<()> {
+    let old = shared.current();
+    let mut cfg = shared.source.load()?;
+    keep_restart_only(&old.config, &mut cfg);
+    let toolchains = toolchain::probe_all(&cfg).await?;
+
+    // The new pools find the runtimes already baked on disk; the rest are
+    // baked in the background without touching readiness.
+    let unbaked = toolchains
+        .values()
+        .any(|toolchain| toolchain.builds.baked_slots() < toolchain.builds.slots());
+    let examples_changed = cfg.warm_up.examples != old.config.warm_up.examples;
+    let shares = if cfg.share == old.config.share {
+        old.shares.clone()
+    } else {
+        Arc::new(ShareStore::open(&cfg.share)?)
+    };
+
+    let state = AppState {
+        limits: Arc::new(old.limits.reconfigure(&cfg.rate_limit)),
+        keys: Arc::new(KeyStore::new(&cfg.auth)),
+        config: Arc::new(cfg),
+        cache: old.cache.clone(),
+        toolchains: Arc::new(toolchains),
+        shares,
+        warm_up: old.warm_up.clone(),
+    };
+    shared.replace(state.clone());
+    if state.config.warm_up.enabled && (unbaked || examples_changed) {
+        tokio::spawn(warm_up::refresh(state, examples_changed));
+    }
+    Ok(())
+}
+
+/// Keep the settings only a restart applies, warning about changes.
+fn keep_restart_only(old: &Config, new: &mut Config) {
+    if new.bind_addr != old.bind_addr {
+        tracing::warn!("bind_addr changed; restart to apply");
+        new.bind_addr = old.bind_addr;
+    }
+    if new.limits.max_body_bytes != old.limits.max_body_bytes {
+        tracing::warn!("limits.max_body_bytes changed; restart to apply");
+        new.limits.max_body_bytes = old.limits.max_body_bytes;
+    }
+    if new.compiler.cache_dir() != old.compiler.cache_dir()
+        || new.compiler.cache_max_bytes != old.compiler.cache_max_bytes
+    {
+        tracing::warn!("compile cache location or size changed; restart to apply");
+        new.compiler.cache_dir = Some(old.compiler.cache_dir());
+        new.compiler.cache_max_bytes = old.compiler.cache_max_bytes;
+    }
+}
+
+/// Reload on every `SIGHUP`.
+#[cfg(unix)]
+pub async fn watch_sighup(shared: SharedState) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(error) => {
+            tracing::warn!("cannot listen for SIGHUP: {error}");
+            return;
+        }
+    };
+    while hangups.recv().await.is_some() {
+        // Failures are logged; the old config keeps serving.
+        let _ = reload(&shared, "SIGHUP").await;
+    }
+}
+
+#[derive(Serialize)]
+struct Reloaded {
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// `POST /api/admin/reload`: needs a key with `admin = true`, or, with
+/// authentication off, a request from the loopback interface that no proxy
+/// forwarded.
+pub async fn handle(
+    State(shared): State<SharedState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    key: ApiKey,
+    headers: HeaderMap,
+) -> Response {
+    let allowed = match &key {
+        Some(Extension(key)) => key.admin,
+        None => {
+            let forwarded_header = &shared.current().config.rate_limit.client_ip_header;
+            peer.ip().is_loopback() && !headers.contains_key(forwarded_header.as_str())
+        }
+    };
+    if !allowed {
+        let body = Reloaded {
+            success: false,
+            error: Some("reloading the config needs an admin API key".to_owned()),
+        };
+        return (StatusCode::FORBIDDEN, Json(body)).into_response();
+    }
+    match reload(&shared, "admin endpoint").await {
+        Ok(()) => Json(Reloaded {
+            success: true,
+            error: None,
+        })
+        .into_response(),
+        Err(error) => {
+            let body = Reloaded {
+                success: false,
+                error: Some(format!("{error:#}")),
+            };
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response()
+        }
+    }
+}
@@ -21,10 +21,9 @@ use tokio::process::Command;
 /// Return a `Command` that runs `program args` inside the configured sandbox.
 ///
 /// * `work_dir`       — the shared compiler build directory; gets full
-///                      read-write access for request sources, caches, and
-///                      outputs.
+///   read-write access for request sources, caches, and outputs.
 /// * `extra_ro_paths` — additional paths that need read-only access (e.g., the
-///                      parent directory of the compiler binary).
+///   parent directory of the compiler binary).
 pub fn wrap<I, S>(
     cfg: &SandboxConfig,
     program: &Path,
@@ -0,0 +1,391 @@
+//! `/api/share` request handlers and the snippet store behind them.
+//!
+//! Snippets are stored as one JSON file per ID under `share.dir`. IDs are a
+//! URL-safe base64 prefix of the snippet's SHA-256, so sharing the same code
+//! twice yields the same link; on the rare prefix collision the ID grows
+//! until it is unique. With `share.expiry_secs` set, snippets not shared
+//! again within that window are deleted when next requested, at startup, and
+//! by a periodic sweep. `share.max_total_bytes` caps the whole store, so
+//! anonymous clients cannot fill the disk.
+//!
+//! File IO runs on the blocking pool, as in the compile cache.
+
+use crate::config::ShareConfig;
+use crate::state::{AppState, SharedState};
+use anyhow::{Context, Result};
+use axum::extract::{Path as UrlPath, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as B64_URL, Engine};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tempfile::NamedTempFile;
+
+const MIN_ID_LEN: usize = 10;
+
+/// How often expired snippets are swept.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Shared playground state. Mirrors the fields of a compile request so a
+/// snippet can be replayed as-is.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Snippet {
+    #[serde(default)]
+    source: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    files: BTreeMap<String, String>,
+    mode: String,
+    opt: String,
+    #[serde(default)]
+    reuse_across_call: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    debug: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    toolchain: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Created {
+    id: String,
+}
+
+pub async fn create(State(state): State<AppState>, Json(snippet): Json<Snippet>) -> Response {
+    match state.shares.insert(snippet).await {
+        Ok(id) => Json(Created { id }).into_response(),
+        Err(error) if error.downcast_ref::<TooLarge>().is_some() => {
+            (StatusCode::PAYLOAD_TOO_LARGE, error.to_string()).into_response()
+        }
+        Err(error) if error.downcast_ref::<StoreFull>().is_some() => {
+            tracing::warn!("{error}");
+            (StatusCode::INSUFFICIENT_STORAGE, error.to_string()).into_response()
+        }
+        Err(error) => {
+            tracing::error!("cannot store snippet: {error:#}");
+            (StatusCode::INTERNAL_SERVER_ERROR, "cannot store snippet").into_response()
+        }
+    }
+}
+
+pub async fn get(State(state): State<AppState>, UrlPath(id): UrlPath<String>) -> Response {
+    match state.shares.get(id.clone()).await {
+        Ok(Some(snippet)) => Json(snippet).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, format!("unknown snippet: {id}")).into_response(),
+        Err(error) => {
+            tracing::error!("cannot read snippet {id}: {error:#}");
+            (StatusCode::INTERNAL_SERVER_ERROR, "cannot read snippet").into_response()
+        }
+    }
+}
+
+/// The serialized snippet exceeds `share.max_bytes`.
+#[derive(Debug)]
+struct TooLarge {
+    size: usize,
+    max: usize,
+}
+
+impl std::fmt::Display for TooLarge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "snippet is {} bytes; the limit is {} bytes",
+            self.size, self.max
+        )
+    }
+}
+
+impl std::error::Error for TooLarge {}
+
+/// The store already holds `share.max_total_bytes`.
+#[derive(Debug)]
+struct StoreFull;
+
+impl std::fmt::Display for StoreFull {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the snippet store is full")
+    }
+}
+
+impl std::error::Error for StoreFull {}
+
+pub struct ShareStore {
+    dir: PathBuf,
+    max_bytes: usize,
+    max_total_bytes: u64,
+    expiry: Option<Duration>,
+    /// Bytes of all stored snippets.
+    total_bytes: Mutex<u64>,
+}
+
+impl ShareStore {
+    /// Open the store in `cfg.dir`, deleting snippets that have expired.
+    pub fn open(cfg: &ShareConfig) -> Result<Self> {
+        std::fs::create_dir_all(&cfg.dir)
+            .with_context(|| format!("cannot create share directory: {}", cfg.dir.display()))?;
+        let store = Self {
+            dir: cfg.dir.clone(),
+            max_bytes: cfg.max_bytes,
+            max_total_bytes: cfg.max_total_bytes,
+            expiry: cfg.expiry_secs.map(Duration::from_secs),
+            total_bytes: Mutex::new(0),
+        };
+        let mut total_bytes = 0;
+        for entry in std::fs::read_dir(&store.dir)
+            .with_context(|| format!("cannot read share directory: {}", store.dir.display()))?
+        {
+            let entry = entry?;
+            let name = entry.file_name();
+            if name.to_str().is_some_and(is_id) && !store.sweep_one(&entry.path()) {
+                total_bytes += entry.metadata().map_or(0, |metadata| metadata.len());
+            }
+        }
+        *store.total_bytes.lock().unwrap() = total_bytes;
+        Ok(store)
+    }
+
+    /// Store `snippet` and return its ID. Sharing an existing snippet again
+    /// restarts its expiry.
+    pub async fn insert(self: &Arc<Self>, snippet: Snippet) -> Result<String> {
+        let store = self.clone();
+        tokio::task::spawn_blocking(move || store.insert_blocking(&snippet)).await?
+    }
+
+    /// Look up a snippet. Unknown, malformed, and expired IDs are `None`.
+    pub async fn get(self: &Arc<Self>, id: String) -> Result<Option<Snippet>> {
+        let store = self.clone();
+        tokio::task::spawn_blocking(move || store.get_blocking(&id)).await?
+    }
+
+    /// Delete every expired snippet.
+    pub async fn sweep(self: &Arc<Self>) -> Result<()> {
+        let store = self.clone();
+        tokio::task::spawn_blocking(move || store.sweep_blocking()).await?
+    }
+
+    fn insert_blocking(&self, snippet: &Snippet) -> Result<String> {
+        let bytes = serde_json::to_vec(snippet).context("cannot serialize snippet")?;
+        if bytes.len() > self.max_bytes {
+            return Err(TooLarge {
+                size: bytes.len(),
+                max: self.max_bytes,
+            }
+            .into());
+        }
+        let hash = B64_URL.encode(Sha256::digest(&bytes));
+        for len in MIN_ID_LEN..=hash.len() {
+            let id = &hash[..len];
+            let path = self.dir.join(id);
+            match std::fs::read(&path) {
+                Ok(existing) if existing == bytes => {
+                    std::fs::File::options()
+                        .write(true)
+                        .open(&path)
+                        .and_then(|file| file.set_modified(SystemTime::now()))
+                        .with_context(|| format!("cannot touch {}", path.display()))?;
+                    return Ok(id.to_owned());
+                }
+                Ok(_) => continue,
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                    let size = bytes.len() as u64;
+                    {
+                        let mut total = self.total_bytes.lock().unwrap();
+                        if *total + size > self.max_total_bytes {
+                            return Err(StoreFull.into());
+                        }
+                        *total += size;
+                    }
+                    let written = NamedTempFile::new_in(&self.dir)
+                        .and_then(|mut file| {
+                            std::io::Write::write_all(&mut file, &bytes)?;
+                            file.persist(&path).map_err(|error| error.error)
+                        })
+                        .with_context(|| format!("cannot write {}", path.display()));
+                    if written.is_err() {
+                        *self.total_bytes.lock().unwrap() -= size;
+                    }
+                    written?;
+                    return Ok(id.to_owned());
+                }
+                Err(error) => {
+                    return Err(error).with_context(|| format!("cannot read {}", path.display()))
+                }
+            }
+        }
+        anyhow::bail!("snippet hash {hash} collides with a different snippet")
+    }
+
+    fn get_blocking(&self, id: &str) -> Result<Option<Snippet>> {
+        if !is_id(id) {
+            return Ok(None);
+        }
+        let path = self.dir.join(id);
+        if self.sweep_one(&path) {
+            return Ok(None);
+        }
+        let bytes = match std::fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => {
+                return Err(error).with_context(|| format!("cannot read {}", path.display()))
+            }
+        };
+        serde_json::from_slice(&bytes)
+            .map(Some)
+            .with_context(|| format!("corrupt snippet {}", path.display()))
+    }
+
+    fn sweep_blocking(&self) -> Result<()> {
+        if self.expiry.is_none() {
+            return Ok(());
+        }
+        for entry in std::fs::read_dir(&self.dir)
+            .with_context(|| format!("cannot read share directory: {}", self.dir.display()))?
+        {
+            let entry = entry?;
+            if entry.file_name().to_str().is_some_and(is_id) {
+                self.sweep_one(&entry.path());
+            }
+        }
+        Ok(())
+    }
+
+    /// Delete the snippet at `path` if it has expired; `true` if it had.
+    fn sweep_one(&self, path: &Path) -> bool {
+        let Some(expiry) = self.expiry else {
+            return false;
+        };
+        let Ok(metadata) = std::fs::metadata(path) else {
+            return false;
+        };
+        let expired = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age > expiry);
+        if expired {
+            match std::fs::remove_file(path) {
+                Ok(()) => {
+                    let mut total = self.total_bytes.lock().unwrap();
+                    *total = total.saturating_sub(metadata.len());
+                }
+                Err(error) => {
+                    tracing::warn!("cannot remove expired snippet {}: {error}", path.display());
+                }
+            }
+        }
+        expired
+    }
+}
+
+/// Sweep expired snippets from the current store every [`SWEEP_INTERVAL`].
+pub async fn sweep_expired(shared: SharedState) {
+    let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+    loop {
+        interval.tick().await;
+        let shares = shared.current().shares.clone();
+        if let Err(error) = shares.sweep().await {
+            tracing::warn!("cannot sweep expired snippets: {error:#}");
+        }
+    }
+}
+
+fn is_id(name: &str) -> bool {
+    (MIN_ID_LEN..=43).contains(&name.len())
+        && name
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(
+        dir: &tempfile::TempDir,
+        max_bytes: usize,
+        max_total_bytes: u64,
+        expiry_secs: Option<u64>,
+    ) -> Arc<ShareStore> {
+        Arc::new(
+            ShareStore::open(&ShareConfig {
+                dir: dir.path().to_owned(),
+                max_bytes,
+                max_total_bytes,
+                expiry_secs,
+            })
+            .unwrap(),
+        )
+    }
+
+    fn snippet(source: &str) -> Snippet {
+        Snippet {
+            source: source.to_owned(),
+            files: BTreeMap::new(),
+            mode: "run".to_owned(),
+            opt: "none".to_owned(),
+            reuse_across_call: false,
+            debug: false,
+            toolchain: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn ids_are_stable_and_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = store(&dir, 1024, 4096, None);
+        let id = store.insert(snippet("a")).await.unwrap();
+        assert_eq!(id.len(), MIN_ID_LEN);
+        assert_eq!(store.insert(snippet("a")).await.unwrap(), id);
+        assert_ne!(store.insert(snippet("b")).await.unwrap(), id);
+        assert_eq!(store.get(id).await.unwrap(), Some(snippet("a")));
+        let traversal = "../../etc/passwd".to_owned();
+        assert_eq!(store.get(traversal).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn rejects_oversized_snippets() {
+        let dir = tempfile::tempdir().unwrap();
+        let error = store(&dir, 16, 4096, None)
+            .insert(snippet("a"))
+            .await
+            .unwrap_err();
+        assert!(error.downcast_ref::<TooLarge>().is_some());
+    }
+
+    #[tokio::test]
+    async fn rejects_snippets_past_the_store_cap() {
+        let dir = tempfile::tempdir().unwrap();
+        let size = serde_json::to_vec(&snippet("a")).unwrap().len() as u64;
+        let store = store(&dir, 1024, size, None);
+        let id = store.insert(snippet("a")).await.unwrap();
+        assert_eq!(store.insert(snippet("a")).await.unwrap(), id);
+        let error = store.insert(snippet("b")).await.unwrap_err();
+        assert!(error.downcast_ref::<StoreFull>().is_some());
+    }
+
+    #[tokio::test]
+    async fn expired_snippets_are_swept() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = store(&dir, 1024, 4096, Some(60));
+        let stale_id = store.insert(snippet("a")).await.unwrap();
+        let fresh_id = store.insert(snippet("b")).await.unwrap();
+        let stale = SystemTime::now() - Duration::from_secs(120);
+        std::fs::File::options()
+            .write(true)
+            .open(dir.path().join(&stale_id))
+            .unwrap()
+            .set_modified(stale)
+            .unwrap();
+        store.sweep().await.unwrap();
+        assert!(!dir.path().join(&stale_id).exists());
+        assert_eq!(store.get(stale_id).await.unwrap(), None);
+        assert_eq!(store.get(fresh_id).await.unwrap(), Some(snippet("b")));
+        let fresh = serde_json::to_vec(&snippet("b")).unwrap().len() as u64;
+        assert_eq!(*store.total_bytes.lock().unwrap(), fresh);
+    }
+}
@@ -0,0 +1,234 @@
+//! Size breakdown of run-mode modules.
+//!
+//! Sizes come from the stripped module the browser receives. `llvm-strip`
+//! drops the `name` section but keeps function indices, so function names
+//! are read from the unstripped Rene artifact. A function counts as user code
+//! when its name mentions the request's package name; every other named
+//! function is attributed to the Reussir runtime and the Rust and WASI
+//! libraries linked with it.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use wasmparser::{KnownCustom, Name, Parser, Payload, TypeRef};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SizeReport {
+    /// Size of the stripped module.
+    pub total: u64,
+    /// Section payload sizes in module order.
+    pub sections: Vec<SectionSize>,
+    /// Defined functions by body size, largest first.
+    pub functions: Vec<FunctionSize>,
+    pub data_segments: Vec<DataSegmentSize>,
+    /// Code section bytes by origin.
+    pub code: CodeAttribution,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SectionSize {
+    pub name: String,
+    pub size: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FunctionSize {
+    pub index: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub size: u64,
+    pub origin: Origin,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Origin {
+    User,
+    Runtime,
+    /// The unstripped module has no name for this function.
+    Unknown,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DataSegmentSize {
+    pub index: u32,
+    pub size: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct CodeAttribution {
+    pub user: u64,
+    pub runtime: u64,
+    pub unknown: u64,
+}
+
+/// Measure `stripped`, naming functions from `unstripped`.
+pub fn report(stripped: &[u8], unstripped: &[u8], package_name: &str) -> Result<SizeReport> {
+    let names = function_names(unstripped).context("cannot read function names")?;
+    let mut report = SizeReport {
+        total: stripped.len() as u64,
+        sections: Vec::new(),
+        functions: Vec::new(),
+        data_segments: Vec::new(),
+        code: CodeAttribution::default(),
+    };
+    let mut imported_functions = 0;
+    let mut next_function = 0;
+    for payload in Parser::new(0).parse_all(stripped) {
+        let payload = payload.context("malformed WASM module")?;
+        if let Some(name) = section_name(&payload) {
+            let (_, range) = payload.as_section().expect("named payloads are sections");
+            report.sections.push(SectionSize {
+                name,
+                size: range.end - range.start,
+            });
+        }
+        match payload {
+            Payload::ImportSection(reader) => {
+                for import in reader.into_imports() {
+                    if matches!(import?.ty, TypeRef::Func(_) | TypeRef::FuncExact(_)) {
+                        imported_functions += 1;
+                    }
+                }
+                next_function = imported_functions;
+            }
+            Payload::CodeSectionEntry(body) => {
+                let index = next_function;
+                next_function += 1;
+                let range = body.range();
+                let size = range.end - range.start;
+                let name = names.get(&index).cloned();
+                let origin = match &name {
+                    Some(name) if name.contains(package_name) => Origin::User,
+                    Some(_) => Origin::Runtime,
+                    None => Origin::Unknown,
+                };
+                match origin {
+                    Origin::User => report.code.user += size,
+                    Origin::Runtime => report.code.runtime += size,
+                    Origin::Unknown => report.code.unknown += size,
+                }
+                report.functions.push(FunctionSize {
+                    index,
+                    name,
+                    size,
+                    origin,
+                });
+            }
+            Payload::DataSection(reader) => {
+                for (index, data) in (0..).zip(reader) {
+                    report.data_segments.push(DataSegmentSize {
+                        index,
+                        size: data?.data.len() as u64,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    report
+        .functions
+        .sort_by(|a, b| b.size.cmp(&a.size).then(a.index.cmp(&b.index)));
+    Ok(report)
+}
+
+fn section_name(payload: &Payload<'_>) -> Option<String> {
+    let name = match payload {
+        Payload::TypeSection(_) => "type",
+        Payload::ImportSection(_) => "import",
+        Payload::FunctionSection(_) => "function",
+        Payload::TableSection(_) => "table",
+        Payload::MemorySection(_) => "memory",
+        Payload::TagSection(_) => "tag",
+        Payload::GlobalSection(_) => "global",
+        Payload::ExportSection(_) => "export",
+        Payload::StartSection { .. } => "start",
+        Payload::ElementSection(_) => "element",
+        Payload::DataCountSection { .. } => "datacount",
+        Payload::DataSection(_) => "data",
+        Payload::CodeSectionStart { .. } => "code",
+        Payload::CustomSection(reader) => return Some(format!("custom:{}", reader.name())),
+        _ => return None,
+    };
+    Some(name.to_owned())
+}
+
+fn function_names(module: &[u8]) -> Result<HashMap<u32, String>> {
+    let mut names = HashMap::new();
+    for payload in Parser::new(0).parse_all(module) {
+        let Payload::CustomSection(reader) = payload? else {
+            continue;
+        };
+        let KnownCustom::Name(reader) = reader.as_known() else {
+            continue;
+        };
+        for subsection in reader {
+            if let Name::Function(map) = subsection? {
+                for naming in map {
+                    let naming = naming?;
+                    names.insert(naming.index, naming.name.to_owned());
+                }
+            }
+        }
+    }
+    Ok(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `(module (import "env" "f" (func)) (func $pkg_main) (func $rt_alloc
+    /// i32.const 0 drop) (data (i32.const 0) "abc"))` with a name section,
+    /// assembled by hand.
+    const NAMED: &[u8] = &[
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+        0x01, 0x04, 0x01, 0x60, 0x00, 0x00, // type: () -> ()
+        0x02, 0x09, 0x01, 0x03, b'e', b'n', b'v', 0x01, b'f', 0x00, 0x00, // import
+        0x03, 0x03, 0x02, 0x00, 0x00, // function
+        0x05, 0x03, 0x01, 0x00, 0x01, // memory
+        0x0a, 0x0a, 0x02, 0x02, 0x00, 0x0b, 0x05, 0x00, 0x41, 0x00, 0x1a, 0x0b, // code
+        0x0b, 0x09, 0x01, 0x00, 0x41, 0x00, 0x0b, 0x03, b'a', b'b', b'c', // data
+        0x00, 0x1c, 0x04, b'n', b'a', b'm', b'e', // custom "name"
+        0x01, 0x15, 0x02, // function names
+        0x01, 0x08, b'p', b'k', b'g', b'_', b'm', b'a', b'i', b'n', //
+        0x02, 0x08, b'r', b't', b'_', b'a', b'l', b'l', b'o', b'c',
+    ];
+
+    #[test]
+    fn attributes_functions_by_name() {
+        let stripped = &NAMED[..NAMED.len() - 30];
+        let report = report(stripped, NAMED, "pkg").unwrap();
+        assert_eq!(report.total, stripped.len() as u64);
+        let sections: Vec<_> = report.sections.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(
+            sections,
+            ["type", "import", "function", "memory", "code", "data"]
+        );
+        assert_eq!(report.functions[0].name.as_deref(), Some("rt_alloc"));
+        assert_eq!(report.functions[0].origin, Origin::Runtime);
+        assert_eq!(report.functions[1].index, 1);
+        assert_eq!(report.functions[1].origin, Origin::User);
+        assert_eq!(
+            report.code,
+            CodeAttribution {
+                user: 2,
+                runtime: 5,
+                unknown: 0
+            }
+        );
+        assert_eq!(report.data_segments[0].size, 3);
+    }
+
+    #[test]
+    fn exact_function_imports_offset_indices() {
+        let mut module = NAMED.to_vec();
+        assert_eq!(module[23], 0x00, "import kind byte");
+        module[23] = 0x20;
+        let stripped = &module[..module.len() - 30];
+        let report = report(stripped, &module, "pkg").unwrap();
+        assert_eq!(report.functions[0].name.as_deref(), Some("rt_alloc"));
+        assert_eq!(report.functions[1].index, 1);
+        assert_eq!(report.functions[1].name.as_deref(), Some("pkg_main"));
+    }
+}
@@ -0,0 +1,73 @@
+//! Shared state handed to every route handler.
+
+use crate::auth::KeyStore;
+use crate::cache::CompileCache;
+use crate::config::{Config, ConfigSource};
+use crate::rate_limit::RateLimiter;
+use crate::share::ShareStore;
+use crate::toolchain::Toolchain;
+use crate::warm_up::WarmUp;
+use anyhow::{Context, Result};
+use axum::extract::FromRef;
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+
+#[derive(Clone)]
+pub struct AppState {
+    pub config: Arc<Config>,
+    pub cache: Arc<CompileCache>,
+    /// Capabilities probed from every configured toolchain at startup, keyed
+    /// by name.
+    pub toolchains: Arc<BTreeMap<String, Toolchain>>,
+    pub shares: Arc<ShareStore>,
+    pub limits: Arc<RateLimiter>,
+    pub keys: Arc<KeyStore>,
+    pub warm_up: Arc<WarmUp>,
+}
+
+impl AppState {
+    /// Look up a toolchain by name, or the configured default when `name` is
+    /// `None`.
+    pub fn toolchain(&self, name: Option<&str>) -> Result<&Toolchain> {
+        let name = name.unwrap_or(&self.config.compiler.default_toolchain);
+        self.toolchains
+            .get(name)
+            .with_context(|| format!("unknown toolchain: {name}"))
+    }
+}
+
+/// The router's state: the current [`AppState`], replaced wholesale when the
+/// config is reloaded. Handlers extract an [`AppState`] snapshot, so a request
+/// keeps the config it started with.
+#[derive(Clone)]
+pub struct SharedState {
+    current: Arc<RwLock<AppState>>,
+    /// Where to reload the config from.
+    pub source: Arc<ConfigSource>,
+    /// Held while a reload runs, so reloads never interleave.
+    pub reloading: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl SharedState {
+    pub fn new(state: AppState, source: ConfigSource) -> Self {
+        Self {
+            current: Arc::new(RwLock::new(state)),
+            source: Arc::new(source),
+            reloading: Default::default(),
+        }
+    }
+
+    pub fn current(&self) -> AppState {
+        self.current.read().unwrap().clone()
+    }
+
+    pub fn replace(&self, state: AppState) {
+        *self.current.write().unwrap() = state;
+    }
+}
+
+impl FromRef<SharedState> for AppState {
+    fn from_ref(shared: &SharedState) -> Self {
+        shared.current()
+    }
+}
@@ -0,0 +1,404 @@
+//! Toolchain capability probing and the `/api/toolchain` request handler.
+//!
+//! Every configured toolchain is probed once at startup: Rene's CLI flavour,
+//! the `--emit` kinds and optimization levels rrc advertises in `--help`, the
+//! Rust targets installed in rustc's sysroot, and every tool's `--version`.
+//! Compiles read the result from shared state instead of re-probing, and an
+//! incompatible toolchain stops the server before it starts listening.
+//!
+//! `/api/toolchain` returns the probe result for the default toolchain, or the
+//! one named by `?name=`, so clients can offer only the supported modes and
+//! optimization levels, and so bug reports can quote the exact nightly in
+//! use. A tool that cannot report its version shows up as `null`.
+
+use crate::build_pool::BuildPool;
+use crate::compile::{self, OPT_LEVELS, RUN_MODES, TARGET};
+use crate::config::{Config, EmitModeConfig, SandboxKind, ToolchainConfig};
+use crate::progress::Progress;
+use crate::state::AppState;
+use anyhow::{Context, Result};
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ReneCli {
+    /// Nightlies before machine targets moved to `--target <TRIPLE>`.
+    Legacy,
+    /// Current Rene: `--target <TRIPLE>` selects the machine target.
+    Modern,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Toolchain {
+    pub name: String,
+    /// Binaries and build directory this probe result describes.
+    #[serde(skip)]
+    pub config: ToolchainConfig,
+    /// Build directories compiles with this toolchain run in.
+    #[serde(skip)]
+    pub builds: Arc<BuildPool>,
+    pub target: &'static str,
+    pub sandbox: SandboxKind,
+    pub rene_cli: ReneCli,
+    pub versions: ToolVersions,
+    /// Request modes this toolchain supports: `run`, `wat`, and every text
+    /// mode whose emit kind rrc advertises.
+    pub modes: Vec<String>,
+    /// The configured text modes this toolchain supports.
+    pub text_modes: Vec<EmitModeConfig>,
+    /// Never empty; the first entry is the fallback for unknown requests.
+    pub opt_levels: Vec<String>,
+    /// `--emit` kinds listed by `rrc --help`.
+    pub emit_kinds: Vec<String>,
+    /// Targets with a standard library in rustc's sysroot.
+    pub rust_targets: Vec<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ToolVersions {
+    pub rrc: Option<String>,
+    pub rene: Option<String>,
+    pub rustc: Option<String>,
+    pub cargo: Option<String>,
+    pub llvm_strip: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ToolchainQuery {
+    name: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ToolchainResponse<'a> {
+    #[serde(flatten)]
+    toolchain: &'a Toolchain,
+    /// Names of every configured toolchain, for the selector.
+    available: Vec<&'a str>,
+}
+
+pub async fn handle(
+    State(state): State<AppState>,
+    Query(query): Query<ToolchainQuery>,
+) -> Response {
+    match state.toolchain(query.name.as_deref()) {
+        Ok(toolchain) => Json(ToolchainResponse {
+            toolchain,
+            available: state.toolchains.keys().map(String::as_str).collect(),
+        })
+        .into_response(),
+        Err(error) => (StatusCode::NOT_FOUND, error.to_string()).into_response(),
+    }
+}
+
+/// Probe every configured toolchain, keyed by name.
+pub async fn probe_all(cfg: &Config) -> Result<BTreeMap<String, Toolchain>> {
+    let mut toolchains = BTreeMap::new();
+    for (name, tc) in &cfg.compiler.toolchains {
+        let toolchain = probe(cfg, name, tc).await?;
+        tracing::info!(
+            "toolchain {name}: rene CLI {:?}, modes {:?}, opt levels {:?}",
+            toolchain.rene_cli,
+            toolchain.modes,
+            toolchain.opt_levels
+        );
+        toolchains.insert(name.clone(), toolchain);
+    }
+    Ok(toolchains)
+}
+
+/// Probe one configured toolchain, failing when it cannot serve the
+/// playground at all.
+pub async fn probe(cfg: &Config, name: &str, tc: &ToolchainConfig) -> Result<Toolchain> {
+    probe_inner(cfg, name, tc)
+        .await
+        .with_context(|| format!("toolchain {name}"))
+}
+
+async fn probe_inner(cfg: &Config, name: &str, tc: &ToolchainConfig) -> Result<Toolchain> {
+    let rustc = compile::resolve_program("rustc", tc.rustc_path.as_deref())?;
+    let cargo = compile::resolve_program("cargo", tc.cargo_path.as_deref())?;
+    let (rene_cli, rrc_help, rust_targets, versions) = tokio::join!(
+        detect_rene_cli(cfg, tc),
+        help_text(cfg, tc, "rrc", &tc.rrc_path, "rrc --help", &["--help"]),
+        installed_targets(cfg, tc, &rustc),
+        async {
+            let (rrc, rene, rustc, cargo, llvm_strip) = tokio::join!(
+                version(cfg, tc, "rrc", &tc.rrc_path),
+                version(cfg, tc, "rene", &tc.rene_path),
+                version(cfg, tc, "rustc", &rustc),
+                version(cfg, tc, "cargo", &cargo),
+                version(cfg, tc, "llvm-strip", &cfg.compiler.llvm_strip_path),
+            );
+            ToolVersions {
+                rrc,
+                rene,
+                rustc,
+                cargo,
+                llvm_strip,
+            }
+        },
+    );
+    let rene_cli = rene_cli.context("incompatible toolchain: cannot inspect rene CLI")?;
+    let rrc_help = rrc_help.context("incompatible toolchain: cannot inspect rrc CLI")?;
+    let rust_targets =
+        rust_targets.context("incompatible toolchain: cannot list installed Rust targets")?;
+    anyhow::ensure!(
+        rust_targets.iter().any(|target| target == TARGET),
+        "incompatible toolchain: {} has no {TARGET} standard library; \
+         run `rustup target add {TARGET}`",
+        rustc.display()
+    );
+
+    let emit_kinds = option_values(&rrc_help, "--emit").unwrap_or_else(|| {
+        tracing::warn!("cannot read --emit kinds from `rrc --help`; assuming configured");
+        cfg.compiler
+            .emit_modes
+            .iter()
+            .map(|mode| mode.emit.clone())
+            .collect()
+    });
+    let mut modes: Vec<String> = RUN_MODES.iter().map(|mode| (*mode).to_owned()).collect();
+    let mut text_modes = Vec::new();
+    for mode in &cfg.compiler.emit_modes {
+        if emit_kinds.contains(&mode.emit) {
+            modes.push(mode.name.clone());
+            text_modes.push(mode.clone());
+        } else {
+            tracing::warn!(
+                "rrc does not support --emit {}; disabling {} mode",
+                mode.emit,
+                mode.name
+            );
+        }
+    }
+
+    let rrc_opt_levels = option_values(&rrc_help, "-O");
+    if rrc_opt_levels.is_none() {
+        tracing::warn!("cannot read -O levels from `rrc --help`; assuming defaults");
+    }
+    let opt_levels: Vec<String> = OPT_LEVELS
+        .iter()
+        .filter(|level| {
+            rrc_opt_levels
+                .as_ref()
+                .is_none_or(|accepted| accepted.iter().any(|a| a == *level))
+        })
+        .map(|level| (*level).to_owned())
+        .collect();
+    anyhow::ensure!(
+        !opt_levels.is_empty(),
+        "incompatible toolchain: rrc accepts none of the optimization levels {OPT_LEVELS:?}"
+    );
+
+    let builds = BuildPool::new(
+        name,
+        &tc.build_dir,
+        cfg.compiler.build_slots,
+        cfg.compiler.queue_depth,
+    )?;
+
+    Ok(Toolchain {
+        name: name.to_owned(),
+        config: tc.clone(),
+        builds: Arc::new(builds),
+        target: TARGET,
+        sandbox: cfg.sandbox.kind,
+        rene_cli,
+        versions,
+        modes,
+        text_modes,
+        opt_levels,
+        emit_kinds,
+        rust_targets,
+    })
+}
+
+async fn detect_rene_cli(cfg: &Config, tc: &ToolchainConfig) -> Result<ReneCli> {
+    let help = help_text(
+        cfg,
+        tc,
+        "rene",
+        &tc.rene_path,
+        "rene build --help",
+        &["build", "--help"],
+    )
+    .await?;
+    Ok(if help.contains("--bin <") {
+        ReneCli::Modern
+    } else {
+        ReneCli::Legacy
+    })
+}
+
+/// Stdout of `program args`; `tool` labels its metrics and `name` its errors.
+async fn help_text(
+    cfg: &Config,
+    tc: &ToolchainConfig,
+    tool: &str,
+    program: &Path,
+    name: &str,
+    args: &[&str],
+) -> Result<String> {
+    let out = compile::run_sandboxed(
+        cfg,
+        tc,
+        tool,
+        program,
+        None,
+        args,
+        Duration::from_secs(cfg.compiler.compile_timeout_secs),
+        &Progress::disabled(),
+    )
+    .await
+    .with_context(|| format!("failed to run {name}"))?;
+    compile::ensure_success(name, &out, None, &tc.build_dir)?;
+    Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+}
+
+/// Targets with a `lib` directory under `<sysroot>/lib/rustlib`.
+async fn installed_targets(
+    cfg: &Config,
+    tc: &ToolchainConfig,
+    rustc: &Path,
+) -> Result<Vec<String>> {
+    let sysroot = help_text(
+        cfg,
+        tc,
+        "rustc",
+        rustc,
+        "rustc --print sysroot",
+        &["--print", "sysroot"],
+    )
+    .await?;
+    let rustlib = PathBuf::from(sysroot.trim()).join("lib").join("rustlib");
+    let mut targets = Vec::new();
+    for entry in
+        std::fs::read_dir(&rustlib).with_context(|| format!("cannot read {}", rustlib.display()))?
+    {
+        let entry = entry?;
+        if entry.path().join("lib").is_dir() {
+            if let Some(name) = entry.file_name().to_str() {
+                targets.push(name.to_owned());
+            }
+        }
+    }
+    targets.sort();
+    Ok(targets)
+}
+
+/// First non-empty line of `<program> --version`.
+async fn version(cfg: &Config, tc: &ToolchainConfig, name: &str, program: &Path) -> Option<String> {
+    let out = compile::run_sandboxed(
+        cfg,
+        tc,
+        name,
+        program,
+        None,
+        ["--version"],
+        Duration::from_secs(cfg.compiler.compile_timeout_secs),
+        &Progress::disabled(),
+    )
+    .await
+    .inspect_err(|error| tracing::warn!("cannot query {name} version: {error:#}"))
+    .ok()?;
+    if !out.status.success() {
+        tracing::warn!("{name} --version exited with {}", out.status);
+        return None;
+    }
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    stdout
+        .lines()
+        .chain(stderr.lines())
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .map(str::to_owned)
+}
+
+/// Values a `--help` text lists for `flag`. Understands clap's
+/// `[possible values: a, b]` and `Possible values:` + `- a` blocks, and
+/// `{a|b}` / `(a|b)` / `<a|b>` alternations.
+fn option_values(help: &str, flag: &str) -> Option<Vec<String>> {
+    let mut lines = help.lines();
+    let first = lines.find(|line| {
+        line.split(|c: char| c.is_whitespace() || c == ',' || c == '=')
+            .any(|word| word == flag)
+    })?;
+    let mut text = first.to_owned();
+    let mut listed = Vec::new();
+    for line in lines {
+        let trimmed = line.trim_start();
+        if let Some(item) = trimmed.strip_prefix("- ") {
+            let value = item.split([':', ' ']).next().unwrap_or_default();
+            listed.push(value.trim().to_owned());
+            continue;
+        }
+        if trimmed.is_empty() || trimmed.starts_with('-') {
+            break;
+        }
+        text.push(' ');
+        text.push_str(trimmed);
+    }
+    if !listed.is_empty() {
+        return Some(listed);
+    }
+
+    if let Some((_, rest)) = text.split_once("[possible values:") {
+        let list = rest.split(']').next()?;
+        return Some(split_values(list, ','));
+    }
+    for (open, close) in [('{', '}'), ('(', ')'), ('<', '>')] {
+        for (start, _) in text.match_indices(open) {
+            let Some(end) = text[start..].find(close) else {
+                continue;
+            };
+            let inner = &text[start + 1..start + end];
+            if inner.contains('|') {
+                return Some(split_values(inner, '|'));
+            }
+        }
+    }
+    None
+}
+
+fn split_values(list: &str, separator: char) -> Vec<String> {
+    list.split(separator)
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_clap_possible_values() {
+        let help = "Options:\n  -o <OUTPUT>  Output path\n      --emit <EMIT>  Output kind \
+                    [default: object]\n          [possible values: llvm-ir, asm, mlir, object]\n  \
+                    -O <OPT>  Optimization [possible values: none, default]\n";
+        assert_eq!(
+            option_values(help, "--emit").unwrap(),
+            ["llvm-ir", "asm", "mlir", "object"]
+        );
+        assert_eq!(option_values(help, "-O").unwrap(), ["none", "default"]);
+    }
+
+    #[test]
+    fn reads_alternations_and_value_lists() {
+        let help = "  --emit ARG    What to emit (llvm-ir|mlir)\n  --opt-level, -O LEVEL\n      \
+                    Possible values:\n      - none: no optimization\n      - size\n  --help\n";
+        assert_eq!(option_values(help, "--emit").unwrap(), ["llvm-ir", "mlir"]);
+        assert_eq!(option_values(help, "-O").unwrap(), ["none", "size"]);
+        assert!(option_values(help, "--target").is_none());
+    }
+}
@@ -0,0 +1,179 @@
+//! Startup warm-up.
+//!
+//! A fresh build directory bakes the Reussir runtime through Cargo on its
+//! first build, which easily takes longer than a user will wait. With
+//! `[warm_up]` enabled, every build directory of every toolchain is baked at
+//! each optimization level before `/readyz` reports ready, and the configured
+//! example sources are compiled into the cache.
+//!
+//! A reload keeps the startup warm-up's readiness and only [`refresh`]es:
+//! directories still lacking a runtime are baked, and changed examples are
+//! compiled, in the background. Warm-ups run one at a time.
+
+use crate::compile::{self, CompileOptions, CompileRequest};
+use crate::config::WarmUpConfig;
+use crate::progress::Progress;
+use crate::state::AppState;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tokio::task::JoinSet;
+
+/// Progress of the warm-up, for `/readyz`.
+#[derive(Debug)]
+pub struct WarmUp {
+    finished: AtomicBool,
+    failures: AtomicUsize,
+    /// Held by the running warm-up or refresh.
+    running: Mutex<()>,
+}
+
+impl WarmUp {
+    /// Already finished when the warm-up is disabled.
+    pub fn new(cfg: &WarmUpConfig) -> Self {
+        Self {
+            finished: AtomicBool::new(!cfg.enabled),
+            failures: AtomicUsize::new(0),
+            running: Mutex::new(()),
+        }
+    }
+
+    pub fn finished(&self) -> bool {
+        self.finished.load(Ordering::Acquire)
+    }
+
+    /// Warm-up builds that failed; their directories bake on first use.
+    pub fn failures(&self) -> usize {
+        self.failures.load(Ordering::Relaxed)
+    }
+
+    fn finish(&self, failures: usize) {
+        self.failures.store(failures, Ordering::Relaxed);
+        self.finished.store(true, Ordering::Release);
+    }
+}
+
+pub async fn run(state: AppState) {
+    let _running = state.warm_up.running.lock().await;
+    let started = Instant::now();
+    tracing::info!("warming up build directories");
+    let mut failures = bake_slots(&state, false).await;
+    failures += compile_examples(&state).await;
+    state.warm_up.finish(failures);
+    match failures {
+        0 => tracing::info!("warm-up finished in {:.1?}", started.elapsed()),
+        _ => tracing::warn!(
+            "warm-up finished in {:.1?} with {failures} failed builds",
+            started.elapsed()
+        ),
+    }
+}
+
+/// After a reload, bake the build directories that hold no runtime yet and,
+/// with `examples_changed`, compile `warm_up.examples`. Waits for a warm-up
+/// still running; readiness is left as the startup warm-up set it.
+pub async fn refresh(state: AppState, examples_changed: bool) {
+    let _running = state.warm_up.running.lock().await;
+    let started = Instant::now();
+    let mut failures = bake_slots(&state, true).await;
+    if examples_changed {
+        failures += compile_examples(&state).await;
+    }
+    match failures {
+        0 => tracing::info!("warm-up refresh finished in {:.1?}", started.elapsed()),
+        _ => tracing::warn!(
+            "warm-up refresh finished in {:.1?} with {failures} failed builds",
+            started.elapsed()
+        ),
+    }
+}
+
+/// Bake every build directory of every toolchain in parallel, or with
+/// `only_unbaked` those without a runtime, returning the failed builds.
+async fn bake_slots(state: &AppState, only_unbaked: bool) -> usize {
+    let mut bakes = JoinSet::new();
+    for (name, toolchain) in state.toolchains.iter() {
+        for index in 0..toolchain.builds.slots() {
+            if only_unbaked && toolchain.builds.is_baked(index) {
+                continue;
+            }
+            let state = state.clone();
+            let name = name.clone();
+            bakes.spawn(async move { bake_slot(&state, &name, index).await });
+        }
+    }
+    let mut failures = 0;
+    while let Some(result) = bakes.join_next().await {
+        failures += result.unwrap_or_else(|error| {
+            tracing::error!("warm-up task failed: {error}");
+            1
+        });
+    }
+    failures
+}
+
+/// Bake build directory `index` of toolchain `name` at every optimization
+/// level, waiting for that directory if a build holds it.
+async fn bake_slot(state: &AppState, name: &str, index: usize) -> usize {
+    let toolchain = &state.toolchains[name];
+    let progress = Progress::disabled();
+    let slot = toolchain.builds.acquire_slot(index).await;
+    let mut failures = 0;
+    for opt in &toolchain.opt_levels {
+        let started = Instant::now();
+        match compile::bake(&state.config, toolchain, &slot, opt, &progress).await {
+            Ok(()) => tracing::info!(
+                "baked {} for toolchain {name} at opt {opt} in {:.1?}",
+                slot.dir().display(),
+                started.elapsed()
+            ),
+            Err(error) => {
+                tracing::warn!(
+                    "cannot bake {} for toolchain {name} at opt {opt}: {error:#}",
+                    slot.dir().display()
+                );
+                failures += 1;
+            }
+        }
+    }
+    failures
+}
+
+/// Compile `warm_up.examples` in run mode at every optimization level of the
+/// default toolchain, caching the results.
+async fn compile_examples(state: &AppState) -> usize {
+    let Ok(toolchain) = state.toolchain(None) else {
+        return 0;
+    };
+    let timeout = Duration::from_secs(state.config.compiler.request_timeout_secs);
+    let mut failures = 0;
+    for path in &state.config.warm_up.examples {
+        let source = match tokio::fs::read_to_string(path).await {
+            Ok(source) => source,
+            Err(error) => {
+                tracing::warn!("cannot read warm-up example {}: {error}", path.display());
+                failures += 1;
+                continue;
+            }
+        };
+        for opt in &toolchain.opt_levels {
+            let req = CompileRequest::new(
+                source.clone(),
+                BTreeMap::new(),
+                "run".to_owned(),
+                CompileOptions::new(opt),
+            );
+            let response = compile::compile(state, &req, timeout, &Progress::disabled()).await;
+            if !response.success {
+                tracing::warn!(
+                    "warm-up example {} failed at opt {opt}: {}",
+                    path.display(),
+                    response.error.as_deref().unwrap_or("unknown error")
+                );
+                failures += 1;
+            }
+        }
+    }
+    failures
+}